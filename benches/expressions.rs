@@ -0,0 +1,37 @@
+use std::time::Instant;
+
+use tarlox::{Executor, WORKERS};
+
+const ITERATIONS: u32 = 2000;
+
+fn bench(executor: &Executor, name: &str, source: &str) {
+    // Parse errors would otherwise be timed as fast results
+    executor.eval_expression_public(source).unwrap();
+
+    let started = Instant::now();
+    for _ in 0..ITERATIONS {
+        executor.eval_expression_public(source).unwrap();
+    }
+
+    println!(
+        "{name:<12} {:?} per evaluation",
+        started.elapsed() / ITERATIONS
+    );
+}
+
+fn main() {
+    let executor = Executor::new(&WORKERS);
+
+    bench(&executor, "sum", "1 + 2 + 3 + 4 + 5 + 6 + 7 + 8");
+    bench(
+        &executor,
+        "mixed",
+        "(1.5 * 3 - 4) / 7 % 2 + 10 * (2 - 0.25)",
+    );
+    bench(
+        &executor,
+        "big",
+        "99999999999999999999 * 99999999999999999999 / 3",
+    );
+    bench(&executor, "comparison", "1 < 2 <= 3 < 4 == (5 > 4)");
+}
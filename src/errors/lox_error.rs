@@ -2,24 +2,43 @@ use core::fmt;
 use std::{
     fmt::Display,
     io::{self},
-    sync::Arc,
 };
 
-use crate::executor::Environment;
-use crate::syntax::Expression;
+use crate::scanner::Span;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum LoxError {
     FileError,
-    UnexceptedCharacter { line: usize, character: char },
-    ParseError { line: Option<usize>, msg: String },
-    RuntimeError { line: Option<usize>, msg: String },
-    UnterminatedString,
+    EncodingError,
+    UnexceptedCharacter {
+        line: usize,
+        character: char,
+    },
+    // The span points at the offending token when there is one
+    ParseError {
+        line: Option<usize>,
+        msg: String,
+        span: Option<Span>,
+    },
+    RuntimeError {
+        line: Option<usize>,
+        msg: String,
+    },
+    // Line where the string starts
+    UnterminatedString(usize),
     InternalError(String),
-    ExceptedExpression(usize),
-    TypeError { excepted_type: String },
+    ExceptedExpression(usize, Option<Span>),
+    TypeError {
+        excepted_type: String,
+    },
+    // Names the types an operator was applied to, like "add" with "number" and "nil"
+    OperandTypeError {
+        operation: &'static str,
+        left: &'static str,
+        right: &'static str,
+    },
     Other(String),
-    Return(Arc<Environment>, Option<Arc<Expression>>),
+    Cancelled,
 }
 
 impl Display for LoxError {
@@ -28,6 +47,7 @@ impl Display for LoxError {
 
         match self {
             FileError => write!(f, "[Lox Error: File can't be accessed.]"),
+            EncodingError => write!(f, "[Lox Error: File is not valid UTF-8 encoding.]"),
             UnexceptedCharacter { line, character } => {
                 write!(f, "[Lox Error: Unexcepted {character} at {line}]")
             }
@@ -38,14 +58,14 @@ impl Display for LoxError {
                     write!(f, "[Runtime Error: Error at end - {msg}]")
                 }
             }
-            ParseError { line, msg } => {
+            ParseError { line, msg, .. } => {
                 if let Some(l) = line {
                     write!(f, "[Parse Error: Error at {l} - {msg}]")
                 } else {
                     write!(f, "[Parse Error: Error at end - {msg}]")
                 }
             }
-            ExceptedExpression(line) => {
+            ExceptedExpression(line, _) => {
                 write!(
                     f,
                     "[Parse Error: Excepted Expression, found nothing ({line})]"
@@ -54,12 +74,19 @@ impl Display for LoxError {
             TypeError { excepted_type } => {
                 write!(f, "[Type Error: Excepted {excepted_type}]")
             }
-            UnterminatedString => {
-                write!(f, "[Lox Error: Unterminated String]")
+            OperandTypeError {
+                operation,
+                left,
+                right,
+            } => {
+                write!(f, "[Type Error: Can't {operation} {left} and {right}]")
+            }
+            UnterminatedString(line) => {
+                write!(f, "[Lox Error: Unterminated String starting at {line}]")
             }
             InternalError(msg) => write!(f, "[Internal Error: {msg}]"),
             Other(txt) => write!(f, "[Unexcepted Error from io::Error - {txt}]"),
-            Return(..) => write!(f, "Unhandled return statement."),
+            Cancelled => write!(f, "[Lox Error: Execution was cancelled]"),
         }
     }
 }
@@ -70,39 +97,54 @@ impl From<io::Error> for LoxError {
 
         match error.kind() {
             NotFound | PermissionDenied => Self::FileError,
+            InvalidData => Self::EncodingError,
             other => Self::Other(other.to_string()),
         }
     }
 }
 
-impl From<&LoxError> for LoxError {
-    fn from(value: &LoxError) -> Self {
-        use LoxError::*;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // Strict copy?
-        // Maybe we can prefer a Rc
-        match value {
-            FileError => FileError,
-            UnterminatedString => UnterminatedString,
-            UnexceptedCharacter { line, character } => UnexceptedCharacter {
-                line: *line,
-                character: *character,
+    #[test]
+    fn test_clone_every_variant() {
+        let errors = [
+            LoxError::FileError,
+            LoxError::EncodingError,
+            LoxError::UnexceptedCharacter {
+                line: 1,
+                character: '@',
+            },
+            LoxError::ParseError {
+                line: Some(2),
+                span: None,
+                msg: "parse".into(),
             },
-            ParseError { line, msg } => ParseError {
-                line: *line,
-                msg: msg.to_owned(),
+            LoxError::RuntimeError {
+                line: None,
+                msg: "runtime".into(),
             },
-            RuntimeError { line, msg } => RuntimeError {
-                line: *line,
-                msg: msg.to_owned(),
+            LoxError::UnterminatedString(3),
+            LoxError::InternalError("internal".into()),
+            LoxError::ExceptedExpression(4, Some(Span { start: 1, end: 2 })),
+            LoxError::TypeError {
+                excepted_type: "Number".into(),
             },
-            InternalError(str) => InternalError(str.to_owned()),
-            ExceptedExpression(line) => ExceptedExpression(*line),
-            TypeError { excepted_type } => TypeError {
-                excepted_type: excepted_type.to_owned(),
+            LoxError::OperandTypeError {
+                operation: "add",
+                left: "number",
+                right: "nil",
             },
-            Other(str) => Other(str.to_owned()),
-            Return(env, expr) => Return(Arc::clone(env), expr.as_ref().map(Arc::clone)),
+            LoxError::Other("other".into()),
+            LoxError::Cancelled,
+        ];
+
+        for error in errors {
+            let copy = error.clone();
+
+            assert_eq!(copy.to_string(), error.to_string());
+            assert_eq!(format!("{copy:?}"), format!("{error:?}"));
         }
     }
 }
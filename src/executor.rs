@@ -1,24 +1,25 @@
 pub mod callable;
 pub mod class;
 pub mod environment;
+pub mod keyed;
+pub mod module;
 pub mod object;
 
 use ahash::AHashMap;
 use dashmap::mapref::one::Ref;
 use dashmap::DashMap;
 use either::Either::{Left, Right};
+use rug::float::Round;
 use threadpool::ThreadPool;
 
-pub use crate::executor::callable::LoxCallable;
 use crate::executor::callable::THIS_KEY;
+pub use crate::executor::callable::{CallSite, FunctionName, LoxCallable, NativeFn};
 use crate::executor::class::LoxClass;
 use crate::Token;
 use crate::GLOBALS;
-use crate::WORKERS;
 pub use object::LoxObject;
 
-use crate::executor::environment::PackagedObject;
-use crate::syntax::expression::LoxLiteral;
+use crate::executor::environment::{PackagedObject, ScopePool};
 use crate::syntax::expression::Operator;
 use crate::syntax::Expression;
 use crate::syntax::Statement;
@@ -27,24 +28,304 @@ use crate::LoxResult;
 use crate::TokenType;
 use crate::TokenType::*;
 pub use environment::Environment;
+use module::{Loading, Module, ModuleLoader};
 
-use std::sync::Arc;
+use crate::resolver::Resolver;
+use crate::syntax::Parser;
+use crate::Scanner;
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
 type LocalsMap = Arc<DashMap<(usize, String), usize, ahash::RandomState>>;
 
-#[derive(Debug, Clone)]
+pub type Output = Arc<Mutex<dyn Write + Send>>;
+
+pub type Input = Arc<Mutex<dyn BufRead + Send>>;
+
+const YIELD_INTERVAL: usize = 1024;
+
+// How a statement finished. Returning and leaving loops aren't errors, so they come back
+// here. A return keeps the scope it was written in, its expression is evaluated there.
+#[derive(Debug)]
+pub enum Flow {
+    Next,
+    Return(Arc<Environment>, Option<Arc<Expression>>),
+    Continue,
+    Break,
+}
+
+// When less than this is left on the stack, evaluation continues on a new segment
+const STACK_RED_ZONE: usize = 256 * 1024;
+const STACK_GROWTH: usize = 8 * 1024 * 1024;
+
+// Code run by `eval` may call `eval` itself, but only this deep
+const MAX_EVAL_DEPTH: usize = 64;
+
+// Lets the host stop a running script from another thread
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone)]
 pub struct Executor {
     environment: Arc<Environment>,
     workers: &'static ThreadPool,
     locals: LocalsMap,
+    output: Output,
+    ids: Arc<AtomicU64>,
+    round: Arc<RwLock<Round>>,
+    deadline: Arc<RwLock<Option<Instant>>>,
+    cancel: CancelToken,
+    trace: Option<Output>,
+    // The outermost environment, where imported files run
+    root: Arc<Environment>,
+    modules: Arc<ModuleLoader>,
+    script: Option<Arc<PathBuf>>,
+    // How many `eval` calls the running code is nested in
+    eval_depth: usize,
+    // Where `breakpoint()` reads its commands from
+    input: Input,
+    // Printed numbers this large or small are written in scientific notation
+    sci_threshold: Option<u32>,
+    // Environments of blocks the resolver found no closures in
+    scopes: ScopePool,
+    // Results of pure natives called with literals, by the id of the call's parenthesis
+    folds: Arc<DashMap<usize, LoxObject, ahash::RandomState>>,
 }
 
 impl Executor {
     pub fn new(workers: &'static ThreadPool) -> Executor {
+        Self::with_output(workers, Arc::new(Mutex::new(io::stdout())))
+    }
+
+    pub fn with_output(workers: &'static ThreadPool, output: Output) -> Executor {
+        let environment = Arc::new(Environment::default());
+
         Self {
-            environment: Arc::new(Environment::default()),
+            root: Arc::clone(&environment),
+            environment,
             workers,
             locals: Arc::new(DashMap::with_hasher(ahash::RandomState::new())),
+            output,
+            ids: Arc::new(AtomicU64::new(0)),
+            round: Arc::new(RwLock::new(Round::Nearest)),
+            deadline: Arc::new(RwLock::new(None)),
+            cancel: CancelToken::default(),
+            trace: None,
+            modules: Arc::new(ModuleLoader::default()),
+            script: None,
+            eval_depth: 0,
+            input: Arc::new(Mutex::new(io::BufReader::new(io::stdin()))),
+            sci_threshold: None,
+            scopes: Arc::new(Mutex::new(vec![])),
+            folds: Arc::new(DashMap::with_hasher(ahash::RandomState::new())),
+        }
+    }
+
+    pub fn with_environment(&self, environment: Arc<Environment>) -> Executor {
+        Executor {
+            environment,
+            ..self.clone()
+        }
+    }
+
+    // Replaces stdin for `breakpoint()`, so debugger sessions can be scripted
+    pub fn with_input(mut self, input: Input) -> Self {
+        self.input = input;
+        self
+    }
+
+    pub fn with_sci_threshold(mut self, sci_threshold: Option<u32>) -> Self {
+        self.sci_threshold = sci_threshold;
+        self
+    }
+
+    // How `print` writes a value
+    pub fn show(&self, value: &LoxObject) -> String {
+        value.to_string_with(self.sci_threshold)
+    }
+
+    // Logs every evaluated statement and expression, nothing is checked when it isn't set
+    pub fn with_trace(mut self, trace: Output) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    // Imports are resolved against the script's directory, and the script can't import itself
+    pub fn with_script(mut self, path: &Path) -> Self {
+        if let Ok((path, Loading::Fresh)) = self.modules.start(path) {
+            self.script = Some(Arc::new(path));
+        }
+        self
+    }
+
+    // Scripts running longer than this are aborted, including their initializers
+    pub fn set_max_time(&self, max_time: Duration) {
+        *self.deadline.write().unwrap() = Some(Instant::now() + max_time);
+    }
+
+    // Forgets every definition, so the REPL can start fresh. Natives from define_native go too.
+    pub fn reset(&self) {
+        self.environment.values.clear();
+        self.environment.natives.clear();
+        self.locals.clear();
+        self.folds.clear();
+        self.modules.clear();
+    }
+
+    pub fn cancel_handle(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    // Checked on every loop iteration and function call
+    pub fn check_interrupt(&self, line: Option<usize>) -> LoxResult<()> {
+        if self.cancel.is_cancelled() {
+            return Err(LoxError::Cancelled);
+        }
+
+        match *self.deadline.read().unwrap() {
+            Some(deadline) if Instant::now() >= deadline => Err(LoxError::RuntimeError {
+                line,
+                msg: "Execution time limit exceeded".into(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // Ids of functions and instances, counted per interpreter to keep the output reproducible
+    pub fn next_id(&self) -> u64 {
+        self.ids.fetch_add(1, Ordering::Relaxed)
+    }
+
+    // Lets programs embedding the interpreter expose their own functions to scripts
+    pub fn define_native(&self, name: &str, arity: usize, fun: NativeFn) {
+        self.put_native(name, arity, fun, false);
+    }
+
+    // For natives that only read their arguments, calls to them may be evaluated in parallel
+    pub fn define_pure_native(&self, name: &str, arity: usize, fun: NativeFn) {
+        self.put_native(name, arity, fun, true);
+    }
+
+    fn put_native(&self, name: &str, arity: usize, fun: NativeFn, pure: bool) {
+        let native = LoxCallable::NativeFunction {
+            arity,
+            fun,
+            pure,
+            variadic: false,
+        };
+
+        environment::put_native(Arc::clone(&self.environment), self, name, native);
+    }
+
+    // Natives may be shadowed in inner scopes, but not redefined in the global one
+    fn check_not_native(&self, token: &Token, name: &str) -> LoxResult<()> {
+        if self.environment.enclosing.is_none() {
+            self.check_not_assigning_native(token, name)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Literals, variables and calls of pure natives on those, nothing else is known to
+    // leave the order of side effects alone
+    fn is_pure(&self, expr: &Expression) -> bool {
+        use Expression::*;
+
+        match expr {
+            Literal(..) | Variable(..) => true,
+            Grouping(inner) => self.is_pure(inner),
+            Call(callee, _, arguments) => {
+                matches!(callee.as_ref(), Variable(name) if self.names_pure_native(name, callee))
+                    && arguments.iter().all(|arg| self.is_pure(arg))
+            }
+            _ => false,
+        }
+    }
+
+    // Natives can't be replaced, so a name that isn't a local and is a pure native's stays one.
+    // The binding is only looked at, reading it could wait for a pending variable.
+    fn names_pure_native(&self, name: &Token, callee: &Expression) -> bool {
+        let TokenType::Identifier(identifier) = &name.kind else {
+            return false;
+        };
+
+        if self.locals.contains_key(&(name.id, callee.to_string())) {
+            return false;
+        }
+
+        let key = environment::env_hash(identifier);
+        [GLOBALS.as_ref(), self.environment.root()].into_iter().any(|env| {
+            env.natives.contains(&key)
+                && env.values.get(&key).is_some_and(|value| {
+                    matches!(value.value(), PackagedObject::Ready(Ok(LoxObject::Callable(native))) if native.is_pure())
+                })
+        })
+    }
+
+    // `expr` is the assignment the resolver saw, its distance is stored under it
+    fn assign_variable(
+        &self,
+        name_tkn: &Token,
+        expr: &Expression,
+        val: LoxObject,
+    ) -> LoxResult<()> {
+        let Identifier(name) = &name_tkn.kind else {
+            return Err(LoxError::InternalError(format!(
+                "Unexcepted Token! Excepted Identifier found {:?}",
+                name_tkn.kind
+            )));
+        };
+        let hash = environment::env_hash(name);
+
+        if let Some(distance) = self.locals.get(&(name_tkn.id, expr.to_string())) {
+            self.environment.assign_at(*distance, hash, val);
+            return Ok(());
+        }
+
+        self.check_not_assigning_native(name_tkn, name)?;
+
+        // The REPL's resolver leaves globals to be found by name
+        let root = self.environment.root();
+        if root.values.contains_key(&hash) {
+            root.values
+                .insert(hash, PackagedObject::Ready(Ok(root.own(val))));
+            return Ok(());
+        }
+
+        Err(LoxError::RuntimeError {
+            line: Some(name_tkn.line),
+            msg: "Undefined variable while assign".into(),
+        })
+    }
+
+    // Assignments that aren't resolved target the globals, wherever they are
+    fn check_not_assigning_native(&self, token: &Token, name: &str) -> LoxResult<()> {
+        let key = environment::env_hash(name);
+
+        if GLOBALS.natives.contains(&key) || self.environment.root().natives.contains(&key) {
+            Err(LoxError::RuntimeError {
+                line: Some(token.line),
+                msg: format!("Native function '{name}' can't be reassigned"),
+            })
+        } else {
+            Ok(())
         }
     }
 
@@ -52,6 +333,31 @@ impl Executor {
         self.locals.insert((id, expr.to_string()), depth);
     }
 
+    // The resolver only asks for natives it knows aren't shadowed. Values that can be
+    // changed, like lists, are left to be made again by every call.
+    pub fn fold_call(&self, paren: &Token, name: &str, arguments: Vec<LoxObject>) {
+        let key = environment::env_hash(name);
+        if !GLOBALS.natives.contains(&key) {
+            return;
+        }
+
+        let Some(Ok(LoxObject::Callable(native))) = environment::wait_value(&GLOBALS, key) else {
+            return;
+        };
+
+        if native.is_pure() {
+            if let Ok(
+                value @ (LoxObject::Nil
+                | LoxObject::Number(..)
+                | LoxObject::LoxString(..)
+                | LoxObject::Boolean(..)),
+            ) = native.call(self, arguments)
+            {
+                self.folds.insert(paren.id, value);
+            }
+        }
+    }
+
     pub fn lookup_variable(
         &self,
         id: usize,
@@ -66,124 +372,237 @@ impl Executor {
         if value.is_some() {
             value
         } else {
-            GLOBALS.get(key)
+            // Natives defined by the host live in the outermost environment
+            GLOBALS
+                .get(key)
+                .or_else(|| self.environment.root().values.get(key))
         }
     }
 
     pub fn execute(&self, statements: Arc<Vec<Arc<Statement>>>) -> LoxResult<()> {
-        for statement in statements.iter() {
-            self.eval_statement(Arc::clone(statement))?;
+        self.execute_block(&statements).map(|_| ())
+    }
+
+    // Stops at the first statement that returns or jumps, and passes that on
+    fn execute_block(&self, statements: &[Arc<Statement>]) -> LoxResult<Flow> {
+        for statement in statements {
+            match self.eval_statement(Arc::clone(statement))? {
+                Flow::Next => (),
+                flow => return Ok(flow),
+            }
         }
 
-        Ok(())
+        Ok(Flow::Next)
     }
 
-    fn eval_statement(&self, stmt: Arc<Statement>) -> LoxResult<()> {
+    // Like `execute`, but gives back the value of a final expression statement
+    // None when the last statement isn't an expression
+    pub fn execute_returning(
+        &self,
+        statements: Arc<Vec<Arc<Statement>>>,
+    ) -> LoxResult<Option<LoxObject>> {
+        let Some((last, rest)) = statements.split_last() else {
+            return Ok(None);
+        };
+
+        if !matches!(self.execute_block(rest)?, Flow::Next) {
+            return Ok(None);
+        }
+
+        if let Statement::StmtExpression(expr) = last.as_ref() {
+            self.eval_expression(expr).map(Some)
+        } else {
+            self.eval_statement(Arc::clone(last))?;
+            Ok(None)
+        }
+    }
+
+    fn eval_statement(&self, stmt: Arc<Statement>) -> LoxResult<Flow> {
         use Statement::*;
 
+        if let Some(trace) = &self.trace {
+            writeln!(trace.lock().unwrap(), "[trace] {}", stmt.kind())?;
+        }
+
         match stmt.as_ref() {
             StmtExpression(expr) => {
                 self.clone().eval_expression(expr)?;
 
-                Ok(())
+                Ok(Flow::Next)
             }
             Print(expr) => {
                 let res = self.clone().eval_expression(expr)?;
 
-                println!("{}", res.to_string());
-                Ok(())
+                writeln!(self.output.lock().unwrap(), "{}", self.show(&res))?;
+                Ok(Flow::Next)
             }
             Var(token, initializer) => {
+                if let TokenType::Identifier(name) = &token.kind {
+                    self.check_not_native(token, name)?;
+                }
+
                 if let Some(expr) = initializer {
                     environment::put(
                         Arc::clone(&self.environment),
-                        Arc::clone(&self.locals),
+                        self,
                         match &token.kind {
                             TokenType::Identifier(name) => name,
                             _ => unreachable!(),
                         },
+                        token.line,
                         Arc::clone(expr),
                     );
 
-                    Ok(())
+                    Ok(Flow::Next)
                 } else {
-                    environment::put_immediately(
-                        Arc::clone(&self.environment),
-                        Arc::clone(&self.locals),
+                    self.environment.values.insert(
                         match &token.kind {
-                            TokenType::Identifier(name) => name,
+                            TokenType::Identifier(name) => environment::env_hash(name),
                             _ => unreachable!(),
                         },
-                        Right(LoxObject::Nil),
+                        PackagedObject::Uninitialized,
                     );
 
-                    Ok(())
+                    Ok(Flow::Next)
                 }
             }
+            // Unlike `var`, the initializer runs right here and its error is reported here
             AwaitVar(token, initializer) => {
+                let name = match &token.kind {
+                    TokenType::Identifier(name) => name,
+                    _ => unreachable!(),
+                };
+                self.check_not_native(token, name)?;
+
                 environment::put_immediately(
                     Arc::clone(&self.environment),
-                    Arc::clone(&self.locals),
-                    match &token.kind {
-                        TokenType::Identifier(name) => name,
-                        _ => unreachable!(),
-                    },
+                    self,
+                    name,
                     Left(initializer),
                 );
 
-                Ok(())
+                match self.environment.values.get(&environment::env_hash(name)) {
+                    Some(value) => match value.value() {
+                        PackagedObject::Ready(Err(e)) => Err(LoxError::RuntimeError {
+                            line: Some(token.line),
+                            msg: format!("Initializer of '{name}' failed - {e}"),
+                        }),
+                        _ => Ok(Flow::Next),
+                    },
+                    None => Ok(Flow::Next),
+                }
             }
-            Block(statements) => {
+            Block(statements, poolable) => {
                 let previous = Arc::clone(&self.environment);
-                let sub_executor = Executor {
-                    workers: self.workers,
-                    environment: Arc::new(Environment::new_with_parent(Arc::clone(&previous))),
-                    locals: Arc::clone(&self.locals),
-                };
 
-                sub_executor.execute(Arc::clone(statements))
+                if !poolable.get() {
+                    let sub_executor =
+                        self.with_environment(Arc::new(Environment::new_with_parent(previous)));
+
+                    return sub_executor.execute_block(statements);
+                }
+
+                let sub_executor =
+                    self.with_environment(environment::take_scope(&self.scopes, previous));
+                let result = sub_executor.execute_block(statements);
+
+                environment::recycle_scope(&self.scopes, sub_executor.environment);
+                result
             }
             If(condition, then_branch, else_branch) => {
                 let condition = bool::from(&self.eval_expression(condition)?);
 
                 if condition {
-                    self.eval_statement(Arc::clone(then_branch))?;
-                } else if else_branch.is_some() {
-                    self.eval_statement(Arc::clone(else_branch.as_ref().unwrap()))?;
+                    self.eval_statement(Arc::clone(then_branch))
+                } else if let Some(else_branch) = else_branch {
+                    self.eval_statement(Arc::clone(else_branch))
+                } else {
+                    Ok(Flow::Next)
                 }
-
-                Ok(())
             }
-            While(condition, body) => {
+            While(condition, body, increments) => {
+                let mut iterations: usize = 0;
+
                 while bool::from(&self.eval_expression(condition)?) {
-                    self.eval_statement(Arc::clone(body))?;
+                    self.check_interrupt(None)?;
+
+                    // Give background initializers a chance on busy loops
+                    iterations = iterations.wrapping_add(1);
+                    if iterations.is_multiple_of(YIELD_INTERVAL) {
+                        thread::yield_now();
+                    }
+
+                    match self.eval_statement(Arc::clone(body))? {
+                        Flow::Next | Flow::Continue => (),
+                        Flow::Break => break,
+                        flow @ Flow::Return(..) => return Ok(flow),
+                    }
+
+                    for increment in increments {
+                        self.eval_expression(increment)?;
+                    }
                 }
 
-                Ok(())
+                Ok(Flow::Next)
             }
-            Function(name, params, body) => {
-                if let TokenType::Identifier(name) = &name.kind {
-                    let fun = LoxCallable::new(Arc::new(params.to_owned()), Arc::clone(body));
+            Repeat(keyword, count, body) => {
+                let count = self.eval_expression(count)?;
+                let count = count.as_usize().map_err(|_| LoxError::RuntimeError {
+                    line: Some(keyword.line),
+                    msg: format!("Repeat count must be a non-negative integer, got {count}"),
+                })?;
+
+                for _ in 0..count {
+                    self.check_interrupt(Some(keyword.line))?;
+
+                    match self.eval_statement(Arc::clone(body))? {
+                        Flow::Next | Flow::Continue => (),
+                        Flow::Break => break,
+                        flow @ Flow::Return(..) => return Ok(flow),
+                    }
+                }
+
+                Ok(Flow::Next)
+            }
+            Continue(_) => Ok(Flow::Continue),
+            Break(_) => Ok(Flow::Break),
+            Import(keyword, path, namespace) => self
+                .eval_import(keyword, path, namespace)
+                .map(|_| Flow::Next),
+            Function(token, params, body) => {
+                if let TokenType::Identifier(name) = &token.kind {
+                    self.check_not_native(token, name)?;
+
+                    let fun = LoxCallable::new(
+                        FunctionName::Named(name.to_owned()),
+                        Arc::new(params.to_owned()),
+                        Arc::clone(body),
+                        self.next_id(),
+                        Arc::clone(&self.environment),
+                    );
                     environment::put_immediately(
                         Arc::clone(&self.environment),
-                        Arc::clone(&self.locals),
+                        self,
                         name,
                         Right(LoxObject::from(fun)),
                     );
-                    Ok(())
+                    Ok(Flow::Next)
                 } else {
                     Err(LoxError::ParseError {
-                        line: Some(name.line),
+                        line: Some(token.line),
+                        span: None,
                         msg: String::from("Invalid name specified in function statement!"),
                     })
                 }
             }
-            Return(maybe_expr) => Err(LoxError::Return(
+            Return(maybe_expr) => Ok(Flow::Return(
                 Arc::clone(&self.environment),
                 maybe_expr.as_ref().map(Arc::clone),
             )),
-            Class(class_name, superclass_expr, methods) => {
+            Class(class_name, superclass_expr, methods_and_fields) => {
                 if let TokenType::Identifier(name) = &class_name.kind {
+                    self.check_not_native(class_name, name)?;
+
                     let mut superclass = None;
 
                     if let Some(superclass_expr) = superclass_expr {
@@ -201,14 +620,18 @@ impl Executor {
                                     });
                                 }
                             }
-                            error => return error.map(|_| ()),
+                            error => return error.map(|_| Flow::Next),
                         }
                     }
 
                     let methods = {
                         let mut result = AHashMap::new();
 
-                        for method in methods {
+                        for method in methods_and_fields {
+                            if let Statement::Var(..) = method {
+                                continue;
+                            }
+
                             if let Statement::Function(
                                 Token {
                                     line: _,
@@ -223,9 +646,15 @@ impl Executor {
                                     method_name.to_owned(),
                                     // Param should be an integer
                                     LoxCallable::new_method(
+                                        FunctionName::Method(
+                                            name.to_string(),
+                                            method_name.to_owned(),
+                                        ),
                                         Arc::new(params.to_owned()),
                                         Arc::clone(body),
                                         method_name == "init",
+                                        self.next_id(),
+                                        Arc::clone(&self.environment),
                                     ),
                                 );
                             } else {
@@ -236,19 +665,45 @@ impl Executor {
                         result
                     };
 
+                    let class = LoxClass::new(
+                        name.to_string(),
+                        superclass,
+                        methods,
+                        Some(Arc::clone(&self.environment)),
+                    );
+
+                    for member in methods_and_fields {
+                        if let Statement::Var(
+                            Token {
+                                kind: TokenType::Identifier(field),
+                                ..
+                            },
+                            initializer,
+                        ) = member
+                        {
+                            let value = match initializer {
+                                Some(initializer) => self.eval_expression(initializer)?,
+                                None => LoxObject::Nil,
+                            };
+
+                            class.static_fields.insert(field.to_owned(), value);
+                        }
+                    }
+
                     environment::put_immediately(
                         Arc::clone(&self.environment),
-                        Arc::clone(&self.locals),
+                        self,
                         name,
                         Right(LoxObject::from(LoxCallable::Class {
-                            class: Arc::new(LoxClass::new(name.to_string(), superclass, methods)),
+                            class: Arc::new(class),
                         })),
                     );
 
-                    Ok(())
+                    Ok(Flow::Next)
                 } else {
                     Err(LoxError::ParseError {
                         line: Some(class_name.line),
+                        span: None,
                         msg: String::from("Invalid name specified in function statement!"),
                     })
                 }
@@ -256,23 +711,38 @@ impl Executor {
         }
     }
 
-    fn eval_expression(&self, expr: &Expression) -> LoxResult<LoxObject> {
+    // Parses and evaluates one expression without statements or output, for benchmarks
+    pub fn eval_expression_public(&self, source: &str) -> LoxResult<LoxObject> {
+        crate::eval_with(source, &mut crate::Resolver::new(self))
+    }
+
+    pub fn eval_expression(&self, expr: &Expression) -> LoxResult<LoxObject> {
+        // Every nested call passes through here, so deep recursion moves to a
+        // bigger stack on the heap instead of overflowing the thread's own
+        let value = stacker::maybe_grow(STACK_RED_ZONE, STACK_GROWTH, || self.evaluate(expr));
+
+        if let (Some(trace), Ok(value)) = (&self.trace, &value) {
+            writeln!(trace.lock().unwrap(), "[trace] {expr} => {value}")?;
+        }
+
+        value
+    }
+
+    fn evaluate(&self, expr: &Expression) -> LoxResult<LoxObject> {
         use Expression::*;
-        use LoxLiteral::*;
 
         match expr {
             Grouping(inner) => self.eval_expression(inner),
-            Literal(Number(n)) => Ok(LoxObject::from(n)),
-            Literal(LoxString(s)) => Ok(LoxObject::from(s.as_str())),
-            Literal(Bool(b)) => Ok(LoxObject::from(*b)),
-            Literal(Nil) => Ok(LoxObject::Nil),
+            Literal(literal) => Ok(LoxObject::from(literal)),
             Unary(Operator::IsReady, right) => {
                 if let Variable(tkn) = right.as_ref() {
                     if let TokenType::Identifier(name) = &tkn.kind {
                         let name = environment::env_hash(name);
                         if let Some(var) = self.environment.get(&name) {
                             match var.value() {
-                                PackagedObject::Ready(_) => Ok(LoxObject::from(true)),
+                                PackagedObject::Ready(_) | PackagedObject::Uninitialized => {
+                                    Ok(LoxObject::from(true))
+                                }
                                 PackagedObject::Pending(..) => Ok(LoxObject::from(false)),
                             }
                         } else {
@@ -285,8 +755,13 @@ impl Executor {
                         unreachable!()
                     }
                 } else {
-                    // R-values are always ready
-                    Ok(LoxObject::from(true))
+                    // Fields and map entries are ready unless they hold a pending future
+                    match self.eval_expression(right)? {
+                        LoxObject::Future(future) => {
+                            Ok(LoxObject::from(environment::is_future_ready(&future)))
+                        }
+                        _ => Ok(LoxObject::from(true)),
+                    }
                 }
             }
             Unary(operator, right) => {
@@ -302,19 +777,22 @@ impl Executor {
                 let left = self.clone().eval_expression(left)?;
                 let right = self.clone().eval_expression(right)?;
 
-                match operator {
-                    Operator::Star => left * right,
-                    Operator::Slash => left / right,
-                    Operator::Minus => left - right,
-                    Operator::Plus => left + right,
-                    Operator::Equality => Ok(left.is_equal(&right)),
-                    Operator::NotEqual => Ok(left.is_not_equal(&right)),
-                    Operator::Greater => left.is_greater(&right),
-                    Operator::GreaterOrEqual => left.is_greater_equal(&right),
-                    Operator::Smaller => left.is_less(&right),
-                    Operator::SmallerOrEqual => left.is_less_equal(&right),
-                    _ => unreachable!(),
+                self.eval_binary(left, operator, right)
+            }
+            Comparison(first, rest) => {
+                // Same as `a < b and b < c`, with `b` kept as a temporary
+                let mut left = self.eval_expression(first)?;
+
+                for (operator, operand) in rest {
+                    let right = self.eval_expression(operand)?;
+
+                    if !bool::from(&self.eval_binary(left, operator, right.clone())?) {
+                        return Ok(LoxObject::from(false));
+                    }
+                    left = right;
                 }
+
+                Ok(LoxObject::from(true))
             }
             Variable(token) => {
                 if let Identifier(name) = &token.kind {
@@ -327,12 +805,24 @@ impl Executor {
                                 PackagedObject::Pending(mtx, cvar) => {
                                     let lock = mtx.lock().unwrap();
 
-                                    let _ = cvar.wait_while(lock, |pending| !*pending);
+                                    drop(cvar.wait_while(lock, |pending| !*pending));
                                 }
                                 PackagedObject::Ready(res) => match res {
                                     Ok(obj) => return Ok(LoxObject::from(obj)),
-                                    Err(e) => return Err(e.into()),
+                                    // The error may come from a worker, so point to the read too
+                                    Err(e) => {
+                                        return Err(LoxError::RuntimeError {
+                                            line: Some(token.line),
+                                            msg: format!("Can't read '{name}' - {e}"),
+                                        })
+                                    }
                                 },
+                                PackagedObject::Uninitialized => {
+                                    return Err(LoxError::RuntimeError {
+                                        line: Some(token.line),
+                                        msg: format!("Variable '{name}' used before assignment"),
+                                    })
+                                }
                             }
                         } else {
                             return Err(LoxError::RuntimeError {
@@ -349,26 +839,26 @@ impl Executor {
                 }
             }
             Assign(name_tkn, value_expr) => {
-                if let Identifier(name) = &name_tkn.kind {
-                    if let Some(distance) = self.locals.get(&(name_tkn.id, expr.to_string())) {
-                        let hash = environment::env_hash(name);
-                        let val = self.clone().eval_expression(value_expr)?;
-                        self.environment
-                            .assign_at(*distance, hash, LoxObject::from(&val));
-
-                        Ok(val)
-                    } else {
-                        Err(LoxError::RuntimeError {
-                            line: Some(name_tkn.line),
-                            msg: "Undefined variable while assign".into(),
-                        })
-                    }
-                } else {
-                    Err(LoxError::InternalError(format!(
-                        "Unexcepted Token! Excepted Identifier found {:?}",
-                        name_tkn.kind
-                    )))
-                }
+                let val = self.clone().eval_expression(value_expr)?;
+                self.assign_variable(name_tkn, expr, LoxObject::from(&val))?;
+
+                Ok(val)
+            }
+            // Reads the variable once, a postfix increment gives back exactly what it read
+            Increment(assign, prefix) => {
+                let Assign(name_tkn, step) = assign.as_ref() else {
+                    unreachable!()
+                };
+                let Binary(target, operator, one) = step.as_ref() else {
+                    unreachable!()
+                };
+
+                let old = self.eval_expression(target)?;
+                let new =
+                    self.eval_binary(LoxObject::from(&old), operator, self.eval_expression(one)?)?;
+                self.assign_variable(name_tkn, assign, LoxObject::from(&new))?;
+
+                Ok(if *prefix { new } else { old })
             }
             Logical(right, operator, left) => {
                 let left = self.clone().eval_expression(left)?;
@@ -384,10 +874,20 @@ impl Executor {
                 self.eval_expression(right)
             }
             Call(callee, paren, arguments) => {
+                if let Some(folded) = self.folds.get(&paren.id) {
+                    return Ok(folded.value().clone());
+                }
+
                 let callee = self.clone().eval_expression(callee)?;
 
                 if let LoxObject::Callable(callee) = callee {
-                    let arguments = {
+                    // Arguments without side effects can be evaluated at the same time
+                    let parallel =
+                        arguments.len() > 1 && arguments.iter().all(|arg| self.is_pure(arg));
+
+                    let values = if parallel {
+                        environment::evaluate_parallel(self, arguments)?
+                    } else {
                         let mut res = vec![];
 
                         for arg in arguments {
@@ -397,15 +897,13 @@ impl Executor {
                         res
                     };
 
-                    let sub_executor = Executor {
-                        workers: &WORKERS,
-                        environment: Arc::new(Environment::new_with_parent(Arc::clone(
-                            &self.environment,
-                        ))),
-                        locals: Arc::clone(&self.locals),
+                    let site = CallSite {
+                        line: Some(paren.line),
+                        arguments,
                     };
 
-                    callee.call(&sub_executor, arguments)
+                    // Functions get a scope from their closure, natives work in the caller's
+                    callee.call_at(self, &site, values)
                 } else {
                     Err(LoxError::RuntimeError {
                         line: Some(paren.line),
@@ -413,9 +911,31 @@ impl Executor {
                     })
                 }
             }
+            Match(keyword, value, arms) => {
+                let value = self.eval_expression(value)?;
+
+                for (pattern, arm) in arms {
+                    let matched = match pattern {
+                        Some(pattern) => bool::from(&value.is_equal(&LoxObject::from(pattern))),
+                        None => true,
+                    };
+
+                    if matched {
+                        return self.eval_expression(arm);
+                    }
+                }
+
+                Err(LoxError::RuntimeError {
+                    line: Some(keyword.line),
+                    msg: format!("No arm of match matched {value}, add a '_' arm"),
+                })
+            }
             Lambda(params, body) => Ok(LoxObject::from(LoxCallable::new(
+                FunctionName::Lambda,
                 Arc::new(params.to_owned()),
                 Arc::clone(body),
+                self.next_id(),
+                Arc::clone(&self.environment),
             ))),
             Get(object, name) => {
                 let object = self.eval_expression(object)?;
@@ -425,13 +945,16 @@ impl Executor {
             Set(object, name, value) => {
                 let object = self.eval_expression(object)?;
 
-                if let LoxObject::Instance(..) = object {
+                let is_class = matches!(&object, LoxObject::Callable(callable)
+                    if matches!(callable.as_ref(), LoxCallable::Class { .. }));
+
+                if is_class || matches!(object, LoxObject::Instance(..)) {
                     let value = self.eval_expression(value)?;
                     object.set(name, value)
                 } else {
                     Err(LoxError::RuntimeError {
                         line: Some(name.line),
-                        msg: "Only instances have fields".into(),
+                        msg: "Only instances and classes have fields".into(),
                     })
                 }
             }
@@ -439,7 +962,7 @@ impl Executor {
                 if let Some(pair) = self.lookup_variable(name.id, &callable::THIS_KEY, expr) {
                     match pair.wait_for_value() {
                         Ok(val) => Ok(LoxObject::from(val)),
-                        Err(e) => Err(e.into()),
+                        Err(e) => Err(e.clone()),
                     }
                 } else {
                     Err(LoxError::InternalError(format!(
@@ -482,4 +1005,1474 @@ impl Executor {
             }
         }
     }
+
+    // Calls a method like `__eq__` when the object is an instance whose class defines it
+    fn call_dunder(
+        &self,
+        object: &LoxObject,
+        name: &str,
+        arguments: Vec<LoxObject>,
+    ) -> Option<LoxResult<LoxObject>> {
+        if let LoxObject::Instance(_, class, ..) = object {
+            let method = class.find_method(name)?;
+            Some(method.bind(object).call(self, arguments))
+        } else {
+            None
+        }
+    }
+
+    // Instances defining `__hash__` are kept in buckets of sets and maps, apart from other keys
+    pub fn hash_key(&self, key: &LoxObject) -> Option<LoxResult<String>> {
+        self.call_dunder(key, "__hash__", vec![])
+            .map(|hash| hash.map(|hash| hash.to_string()))
+    }
+
+    // Keys in the same bucket are told apart with `__eq__`
+    pub fn keys_equal(&self, key: &LoxObject, other: &LoxObject) -> LoxResult<bool> {
+        match self.call_dunder(key, "__eq__", vec![other.clone()]) {
+            Some(result) => Ok(bool::from(&result?)),
+            None => Ok(key == other),
+        }
+    }
+
+    fn eval_binary(
+        &self,
+        left: LoxObject,
+        operator: &Operator,
+        right: LoxObject,
+    ) -> LoxResult<LoxObject> {
+        match operator {
+            Operator::Star
+            | Operator::Slash
+            | Operator::Remainder
+            | Operator::Minus
+            | Operator::Plus => left.calculate(operator, right, *self.round.read().unwrap()),
+            Operator::Equality => match self.call_dunder(&left, "__eq__", vec![right.clone()]) {
+                Some(result) => Ok(LoxObject::from(bool::from(&result?))),
+                None => Ok(left.is_equal(&right)),
+            },
+            Operator::NotEqual => match self.call_dunder(&left, "__eq__", vec![right.clone()]) {
+                Some(result) => Ok(LoxObject::from(!bool::from(&result?))),
+                None => Ok(left.is_not_equal(&right)),
+            },
+            Operator::ShiftLeft | Operator::ShiftRight | Operator::UnsignedShiftRight => {
+                left.shift(operator, &right)
+            }
+            Operator::Greater
+            | Operator::GreaterOrEqual
+            | Operator::Smaller
+            | Operator::SmallerOrEqual => {
+                let dunder = match operator {
+                    Operator::Greater => "__gt__",
+                    Operator::GreaterOrEqual => "__ge__",
+                    Operator::Smaller => "__lt__",
+                    _ => "__le__",
+                };
+
+                match self.call_dunder(&left, dunder, vec![right.clone()]) {
+                    Some(result) => Ok(LoxObject::from(bool::from(&result?))),
+                    None => match operator {
+                        Operator::Greater => left.is_greater(&right),
+                        Operator::GreaterOrEqual => left.is_greater_equal(&right),
+                        Operator::Smaller => left.is_less(&right),
+                        _ => left.is_less_equal(&right),
+                    },
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn eval_import(&self, keyword: &Token, path: &str, namespace: &Option<Token>) -> LoxResult<()> {
+        let path = module::resolve_path(self.script.as_deref().map(PathBuf::as_path), path);
+        let failed = |e: LoxError| match e {
+            LoxError::Cancelled => e,
+            e => LoxError::RuntimeError {
+                line: Some(keyword.line),
+                msg: format!("Can't import '{}' - {e}", path.display()),
+            },
+        };
+
+        let (module, loading) = self.modules.start(&path).map_err(failed)?;
+
+        let loaded = match loading {
+            Loading::Loaded(loaded) => loaded,
+            // A plain import of a running file is a cycle and does nothing
+            Loading::Running if namespace.is_none() => return Ok(()),
+            Loading::Running => {
+                return Err(failed(LoxError::RuntimeError {
+                    line: None,
+                    msg: "The file imports itself".into(),
+                }))
+            }
+            Loading::Fresh => {
+                // With `as`, a fresh environment keeps the file's declarations out of the importer's globals
+                let environment = match namespace {
+                    Some(_) => Arc::new(Environment::new_with_parent(Arc::clone(&self.root))),
+                    None => Arc::clone(&self.root),
+                };
+                let loaded = self
+                    .run_module(module.clone(), Arc::clone(&environment))
+                    .map(|statements| Module {
+                        environment,
+                        names: module::declared_names(&statements),
+                        namespace: None,
+                    });
+
+                self.modules
+                    .finish(module.clone(), loaded.as_ref().ok().cloned());
+                loaded.map_err(failed)?
+            }
+        };
+
+        let Some(namespace) = namespace else {
+            // The file ran for a namespace before, so its declarations are copied to the globals
+            if !Arc::ptr_eq(&loaded.environment, &self.root) {
+                for (name, value) in self.declarations(&loaded).map_err(failed)? {
+                    environment::put_immediately(Arc::clone(&self.root), self, &name, Right(value));
+                }
+            }
+            return Ok(());
+        };
+
+        let value = match loaded.namespace {
+            Some(value) => value,
+            None => {
+                let value = self.namespace(&loaded).map_err(failed)?;
+                self.modules.keep_namespace(&module, value)
+            }
+        };
+
+        if let Identifier(name) = &namespace.kind {
+            environment::put_immediately(Arc::clone(&self.environment), self, name, Right(value));
+        }
+
+        Ok(())
+    }
+
+    fn run_module(
+        &self,
+        module: PathBuf,
+        environment: Arc<Environment>,
+    ) -> LoxResult<Arc<Vec<Arc<Statement>>>> {
+        let source = fs::read_to_string(&module)?;
+        let statements = Parser::new(&Scanner::new(&source).scan_tokens()?).parse()?;
+
+        let executor = Executor {
+            environment,
+            script: Some(Arc::new(module)),
+            ..self.clone()
+        };
+
+        Resolver::new(&executor).resolve(Arc::clone(&statements))?;
+        executor.execute(Arc::clone(&statements))?;
+
+        Ok(statements)
+    }
+
+    // Declarations of an imported file, with their values
+    fn declarations(&self, module: &Module) -> LoxResult<Vec<(String, LoxObject)>> {
+        let mut declarations = Vec::new();
+
+        for name in &module.names {
+            let key = environment::env_hash(name);
+
+            if let Some(value) = environment::wait_value(&module.environment, key) {
+                declarations.push((name.to_owned(), value?));
+            }
+        }
+
+        Ok(declarations)
+    }
+
+    // Declarations of an imported file, as fields of an instance
+    fn namespace(&self, module: &Module) -> LoxResult<LoxObject> {
+        let fields = self
+            .declarations(module)?
+            .into_iter()
+            .collect::<DashMap<_, _, _>>();
+
+        Ok(LoxObject::Instance(
+            self.next_id(),
+            Arc::new(LoxClass::new("module".into(), None, AHashMap::new(), None)),
+            Arc::new(fields),
+        ))
+    }
+
+    pub fn set_round_mode(&self, mode: Round) {
+        *self.round.write().unwrap() = mode;
+    }
+
+    pub fn describe_scopes(&self) -> String {
+        self.environment.describe()
+    }
+
+    // Runs in the current environment, so definitions stay. Only globals are seen from outside.
+    pub fn eval_source(&self, line: Option<usize>, source: &str) -> LoxResult<LoxObject> {
+        if self.eval_depth >= MAX_EVAL_DEPTH {
+            return Err(LoxError::RuntimeError {
+                line,
+                msg: format!("eval is nested deeper than {MAX_EVAL_DEPTH} levels"),
+            });
+        }
+
+        let executor = Executor {
+            eval_depth: self.eval_depth + 1,
+            ..self.clone()
+        };
+        let statements = Parser::new_eval(&Scanner::new(source).scan_tokens()?).parse()?;
+
+        Resolver::new(&executor).resolve(Arc::clone(&statements))?;
+        Ok(executor
+            .execute_returning(statements)?
+            .unwrap_or(LoxObject::Nil))
+    }
+
+    // Reads commands until `continue` or the end of the input
+    pub fn breakpoint(&self, line: Option<usize>) -> LoxResult<LoxObject> {
+        match line {
+            Some(line) => writeln!(self.output.lock().unwrap(), "[breakpoint at {line}]")?,
+            None => writeln!(self.output.lock().unwrap(), "[breakpoint]")?,
+        }
+
+        loop {
+            {
+                let mut output = self.output.lock().unwrap();
+                write!(output, "debug> ")?;
+                output.flush()?;
+            }
+
+            let mut line = String::new();
+            if self.input.lock().unwrap().read_line(&mut line)? == 0 {
+                return Ok(LoxObject::Nil);
+            }
+
+            let message = match line.split_whitespace().collect::<Vec<_>>()[..] {
+                ["continue" | "c"] => return Ok(LoxObject::Nil),
+                ["print" | "p", name] => self.inspect(name),
+                _ => "Commands: print NAME, continue".into(),
+            };
+
+            writeln!(self.output.lock().unwrap(), "{message}")?;
+        }
+    }
+
+    // Looks the name up through the scopes at run time, the resolver doesn't know it
+    fn inspect(&self, name: &str) -> String {
+        let key = environment::env_hash(name);
+        let mut scope = Some(self.environment.as_ref());
+
+        while let Some(env) = scope {
+            if env.values.contains_key(&key) {
+                return match environment::wait_value(env, key) {
+                    Some(Ok(value)) => format!("{name} = {value}"),
+                    Some(Err(e)) => format!("{name} failed - {e}"),
+                    None => format!("{name} is not assigned yet"),
+                };
+            }
+
+            scope = env.enclosing.as_deref();
+        }
+
+        match environment::wait_value(&GLOBALS, key) {
+            Some(Ok(value)) => format!("{name} = {value}"),
+            _ => format!("{name} is not defined"),
+        }
+    }
+}
+
+impl fmt::Debug for Executor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Executor")
+            .field("environment", &self.environment)
+            .field("locals", &self.locals)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::expression::LoxLiteral;
+    use crate::{resolver::Resolver, scanner::Scanner, syntax::Parser, WORKERS};
+    use std::sync::Weak;
+
+    fn run(source: &str) -> LoxResult<String> {
+        let output = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let executor = Executor::with_output(&WORKERS, output.clone());
+        let mut resolver = Resolver::new(&executor);
+
+        let statements = Parser::new(&Scanner::new(source).scan_tokens()?).parse()?;
+        resolver.resolve(Arc::clone(&statements))?;
+        executor.execute(statements)?;
+
+        let output = output.lock().unwrap().clone();
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_passing_assert() {
+        assert!(run("await_var x = 10; assert(x > 5);").is_ok())
+    }
+
+    #[test]
+    fn test_failing_assert_names_expression() {
+        let error = run("await_var x = 1;\nassert(x > 5);").unwrap_err();
+
+        if let LoxError::RuntimeError { line, msg } = error {
+            assert_eq!(line, Some(2));
+            assert!(msg.contains("(> (var Identifier(\"x\")) 5"), "{msg}");
+        } else {
+            panic!("Unexcepted error: {error}")
+        }
+    }
+
+    #[test]
+    fn test_parenthesized_assignment_resolves_like_plain() {
+        for (source, depth) in [
+            (
+                "fun f() { var x = 0; { (x) = 1; x = 2; } print x; } f();",
+                1,
+            ),
+            (
+                "{ var x = 0; { var y = 0; { ((x)) = 1; x = 2; } } print x; }",
+                2,
+            ),
+        ] {
+            let output = Arc::new(Mutex::new(Vec::<u8>::new()));
+            let executor = Executor::with_output(&WORKERS, output.clone());
+
+            let statements = Parser::new(&Scanner::new(source).scan_tokens().unwrap())
+                .parse()
+                .unwrap();
+            Resolver::new(&executor)
+                .resolve(Arc::clone(&statements))
+                .unwrap();
+
+            let depths: Vec<usize> = executor
+                .locals
+                .iter()
+                .filter(|pair| pair.key().1.starts_with("(assign"))
+                .map(|pair| *pair.value())
+                .collect();
+
+            assert_eq!(depths, vec![depth, depth], "{source}");
+
+            executor.execute(statements).unwrap();
+            assert_eq!(output.lock().unwrap().as_slice(), b"2\n");
+        }
+    }
+
+    #[test]
+    fn test_return_block_local_values() {
+        assert_eq!(
+            run("fun f() {
+                   for (var i = 0; i < 5; i = i + 1) { var j = i * 2; if (i == 3) return j; }
+                 }
+                 fun g() { var x = 1; { var x = 2; return x; } }
+                 fun h() { var i = 0; while (true) { i = i + 1; var k = i; if (k == 4) return k * 10; } }
+                 fun count(n, acc) {
+                   if (n == 0) return acc;
+                   { var next = n - 1; return count(next, acc + n); }
+                 }
+                 fun closure() {
+                   for (var i = 0; i < 3; i = i + 1) {
+                     var t = i;
+                     if (t == 1) return lambda() { return t; };
+                   }
+                 }
+                 print f(); print g(); print h(); print count(100, 0); print closure()();")
+            .unwrap(),
+            "6\n2\n40\n5050\n1\n"
+        )
+    }
+
+    #[test]
+    fn test_continue_runs_for_increment() {
+        assert_eq!(
+            run("for (var i = 0; i < 3; i = i + 1) { if (i == 1) continue; print i; }").unwrap(),
+            "0\n2\n"
+        )
+    }
+
+    #[test]
+    fn test_for_with_several_initializers_and_increments() {
+        assert_eq!(
+            run(
+                "for (var i = 0, j = 10; i < j; i = i + 1, j = j - 1) print i * 100 + j;
+                 var a; var b;
+                 for (a = 0, b = 3; a < b; a = a + 1, b = b - 1) { if (a == 0) continue; print a; }
+                 print a; print b;"
+            )
+            .unwrap(),
+            "10\n109\n208\n307\n406\n1\n2\n1\n"
+        );
+        assert!(run("for (var i = 0, ; i < 1; i = i + 1) {}").is_err());
+    }
+
+    #[test]
+    fn test_continue_in_while() {
+        assert_eq!(
+            run("await_var i = 0; while (i < 4) { i = i + 1; if (i == 2) { continue; } print i; }")
+                .unwrap(),
+            "1\n3\n4\n"
+        )
+    }
+
+    #[test]
+    fn test_remainder_and_floor_mod() {
+        assert_eq!(
+            run("print -7 % 3; print floor_mod(-7, 3); print 7 % 3; print 2 + 7 % 4 * 2;").unwrap(),
+            "-1\n2\n1\n8\n"
+        );
+        assert!(run("print \"a\" % 2;").is_err());
+    }
+
+    #[test]
+    fn test_execute_returning() {
+        let returning = |source: &str| {
+            let executor = Executor::with_output(&WORKERS, Arc::new(Mutex::new(io::sink())));
+            let mut resolver = Resolver::new(&executor);
+
+            let statements = Parser::new(&Scanner::new(source).scan_tokens().unwrap())
+                .parse()
+                .unwrap();
+            resolver.resolve(Arc::clone(&statements)).unwrap();
+            executor
+                .execute_returning(statements)
+                .unwrap()
+                .map(|value| value.to_string())
+        };
+
+        assert_eq!(returning("var a = 3; a * 2;").as_deref(), Some("6"));
+        assert_eq!(returning("1; print 2;"), None);
+        assert_eq!(returning("nil;").as_deref(), Some("nil"));
+        assert_eq!(returning(""), None);
+    }
+
+    #[test]
+    fn test_import_missing_file() {
+        let error = run("import \"does/not/exist.lox\";").unwrap_err();
+        assert!(error.to_string().contains("Can't import"), "{error}");
+        assert!(run("import nothing;").is_err());
+    }
+
+    #[test]
+    fn test_variables_inside_functions() {
+        assert_eq!(
+            run(
+                "fun slow(n) { var i = 0; while (i < n) i = i + 1; return n; }
+                 fun background() {
+                     var x = slow(500);
+                     while (!(is_ready x)) {}
+                     return x;
+                 }
+                 print background();
+                 fun blocking() {
+                     await_var y = slow(300);
+                     var ready = is_ready y;
+                     return lambda() { return ready and is_ready y; };
+                 }
+                 print blocking()();
+                 fun counter() {
+                     await_var n = 0;
+                     return lambda() { n = n + 1; return n; };
+                 }
+                 var next = counter();
+                 next();
+                 print next();"
+            )
+            .unwrap(),
+            "500\ntrue\n2\n"
+        );
+    }
+
+    #[test]
+    fn test_await_var_reports_errors_at_declaration() {
+        let error =
+            run("fun f() { await_var x = nil + 1; print \"unreachable\"; } f();").unwrap_err();
+        assert!(error.to_string().contains("Initializer of 'x'"), "{error}");
+
+        // `var` only fails when the value is read
+        assert_eq!(
+            run("fun f() { var x = nil + 1; print \"reached\"; } f();").unwrap(),
+            "reached\n"
+        );
+    }
+
+    #[test]
+    fn test_uninitialized_variables() {
+        let error = run("var x; print x;").unwrap_err();
+        assert!(
+            error.to_string().contains("used before assignment"),
+            "{error}"
+        );
+
+        assert_eq!(
+            run("var x = nil; print x; var y; y = 2; print y; var z; print is_ready z;").unwrap(),
+            "nil\n2\ntrue\n"
+        );
+        assert!(run("fun f() { var local; return local; } f();").is_err());
+    }
+
+    #[test]
+    fn test_natives_cannot_be_reassigned() {
+        for source in [
+            "var clock = 1;",
+            "clock = 1;",
+            "fun clock() {}",
+            "fun f() { clock = 1; } f();",
+        ] {
+            let error = run(source).unwrap_err();
+            assert!(error.to_string().contains("can't be reassigned"), "{error}");
+        }
+
+        assert_eq!(
+            run("{ var clock = 1; print clock; }
+                 fun f(clock) { return clock; } print f(2);
+                 print clock;")
+            .unwrap(),
+            "1\n2\n<fun arity: 0>\n"
+        );
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(
+            run("print eval(\"1 + 2\");
+                 eval(\"fun square(x) { return x * x; }\");
+                 print square(4);
+                 print eval(\"var a = 2; a * 10\");
+                 print eval(\"print 1;\");")
+            .unwrap(),
+            "3\n16\n20\n1\nnil\n"
+        );
+
+        let error = run("fun f() { return eval(\"f()\"); } f();").unwrap_err();
+        assert!(error.to_string().contains("nested deeper"), "{error}");
+        assert!(run("eval(3);").is_err());
+        assert!(run("eval(\"1 +\");").is_err());
+    }
+
+    #[test]
+    fn test_breakpoint_session() {
+        let output = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let input = io::Cursor::new("print x\nprint y\np outer\nprint missing\nstep\ncontinue\n");
+        let executor =
+            Executor::with_output(&WORKERS, output.clone()).with_input(Arc::new(Mutex::new(input)));
+        let mut resolver = Resolver::new(&executor);
+
+        let statements = Parser::new(
+            &Scanner::new(
+                "var outer = \"top\";
+                 fun f() { var x = 41; var y; breakpoint(); return x + 1; }
+                 print f();
+                 breakpoint();",
+            )
+            .scan_tokens()
+            .unwrap(),
+        )
+        .parse()
+        .unwrap();
+        resolver.resolve(Arc::clone(&statements)).unwrap();
+        executor.execute(statements).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output.lock().unwrap().clone()).unwrap(),
+            "[breakpoint at 2]\n\
+             debug> x = 41\n\
+             debug> y is not assigned yet\n\
+             debug> outer = top\n\
+             debug> missing is not defined\n\
+             debug> Commands: print NAME, continue\n\
+             debug> 42\n\
+             [breakpoint at 4]\n\
+             debug> "
+        );
+    }
+
+    #[test]
+    fn test_format_takes_any_number_of_arguments() {
+        assert_eq!(
+            run("print format(\"{} + {} = {}\", 1, 2, 1 + 2);
+                 print format(\"{{}}\");
+                 await_var f = format; print f(\"{} {{text}}\", 1);")
+            .unwrap(),
+            "1 + 2 = 3\n{}\n1 {text}\n"
+        );
+        assert!(run("format(\"{}\");").is_err());
+        assert!(run("format(\"{}\", 1, 2);").is_err());
+        assert!(run("format();").is_err());
+    }
+
+    #[test]
+    fn test_arity() {
+        assert_eq!(
+            run("fun pair(a, b) {}
+                 class Point { init(x, y, z) {} norm(p) {} }
+                 class Empty {}
+                 print arity(pair); print arity(clock); print arity(format);
+                 print arity(Point); print arity(Empty); print arity(Point(1, 2, 3).norm);
+                 print arity(lambda(a) { return a; }); print format;")
+            .unwrap(),
+            "2\n0\nnil\n3\n0\n1\n1\n<fun arity: 1 or more>\n"
+        );
+        assert!(run("arity(1);").is_err());
+    }
+
+    #[test]
+    fn test_closures_keep_pooled_scopes() {
+        // The inner lambda keeps both blocks alive, the other blocks come from the pool
+        assert_eq!(
+            run("var counters = list(); var i = 0;
+                 while (i < 3) {
+                   var start = i * 10;
+                   { var step = i; list_push(counters, lambda() { return start + step; }); }
+                   { var unused = i; }
+                   i = i + 1;
+                 }
+                 print list_get(counters, 0)(); print list_get(counters, 2)();")
+            .unwrap(),
+            "0\n22\n"
+        );
+        assert_eq!(
+            run("var futures = list();
+                 for (var i = 0; i < 3; i = i + 1) {
+                   var n = i;
+                   list_push(futures, spawn(lambda() { return n * 2; }));
+                 }
+                 print await_all(futures);
+                 var total = 0;
+                 for (var i = 0; i < 3; i = i + 1) { var n = i; total = total + n; }
+                 print total;")
+            .unwrap(),
+            "[0, 2, 4]\n3\n"
+        );
+    }
+
+    #[test]
+    fn test_resolver_marks_poolable_blocks() {
+        let executor = Executor::with_output(&WORKERS, Arc::new(Mutex::new(io::sink())));
+        let mut resolver = Resolver::new(&executor);
+
+        let statements = Parser::new(
+            &Scanner::new("{ var a = 1; } { var b = 2; var f = lambda() { return b; }; }")
+                .scan_tokens()
+                .unwrap(),
+        )
+        .parse()
+        .unwrap();
+        resolver.resolve(Arc::clone(&statements)).unwrap();
+
+        let poolable = statements
+            .iter()
+            .map(|statement| match statement.as_ref() {
+                Statement::Block(_, poolable) => poolable.get(),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(poolable, [true, false]);
+    }
+
+    #[test]
+    fn test_len_respects_shadowing() {
+        assert_eq!(
+            run("print len(\"héllo\") + len(\"a\" + \"b\");
+                 fun first(len) { return len(\"abc\"); }
+                 print first(lambda(s) { return 0; });")
+            .unwrap(),
+            "7\n0\n"
+        );
+        assert_eq!(
+            run("{ var len = lambda(s) { return s; }; print len(\"abc\"); }").unwrap(),
+            "abc\n"
+        );
+    }
+
+    #[test]
+    fn test_len_folds_to_literal() {
+        let executor = Executor::new(&WORKERS);
+        let statements = Parser::new(
+            &Scanner::new(
+                "print len(\"abc\");
+                 { var len = lambda(s) { return s; }; print len(\"abc\"); }
+                 print len(list()); print clock();",
+            )
+            .scan_tokens()
+            .unwrap(),
+        )
+        .parse()
+        .unwrap();
+        Resolver::new(&executor).resolve(statements).unwrap();
+
+        let folds = executor
+            .folds
+            .iter()
+            .map(|fold| fold.value().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(folds, ["3"]);
+    }
+
+    #[test]
+    fn test_function_names() {
+        assert_eq!(
+            run("fun add(a, b) { return a + b; }
+                 class Point { norm() {} }
+                 var alias = add;
+                 print add; print alias; print lambda(x) { return x; };
+                 print Point().norm; print clock;")
+            .unwrap(),
+            "<fun add arity: 2>\n<fun add arity: 2>\n<lambda arity: 1>\n<method Point.norm>\n<fun arity: 0>\n"
+        );
+    }
+
+    #[test]
+    fn test_match() {
+        assert_eq!(
+            run("fun name(n) {
+                   return match n { 1 => \"one\", -2 => \"minus two\", \"x\" => \"ex\", _ => \"other\" };
+                 }
+                 print name(1); print name(-2); print name(\"x\"); print name(7);
+                 print match nil { false => 1, nil => 2, _ => 3, } + 1;
+                 var calls = 0;
+                 fun count() { calls = calls + 1; return 2; }
+                 print match count() { 1 => \"a\", 2 => \"b\" }; print calls;")
+            .unwrap(),
+            "one\nminus two\nex\nother\n3\nb\n1\n"
+        );
+        let error = run("print match 5 { 1 => 1, 2 => 2 };").unwrap_err();
+        assert!(
+            error.to_string().contains("No arm of match matched 5"),
+            "{error}"
+        );
+        assert!(run("print match 1 { x => 1 };").is_err());
+        assert!(run("print match 1 { 1 => 1 2 => 2 };").is_err());
+    }
+
+    #[test]
+    fn test_clone() {
+        assert_eq!(
+            run("class Point {}
+                 var p = Point(); p.x = 1; p.tags = list();
+                 var q = clone(p); q.x = 2; list_push(q.tags, \"a\");
+                 var r = deep_clone(p); r.x = 3; list_push(r.tags, \"b\");
+                 print p.x; print len(p.tags); print len(r.tags);
+                 print p == q; print r.x;")
+            .unwrap(),
+            "1\n1\n2\nfalse\n3\n"
+        );
+        assert_eq!(
+            run("var l = list(); list_push(l, l); list_push(l, 1);
+                 var copy = deep_clone(l); list_push(copy, 2);
+                 print len(l); print len(list_get(copy, 0)); print list_get(copy, 0) == copy;")
+            .unwrap(),
+            "2\n3\ntrue\n"
+        );
+        assert_eq!(run("print clone(1) + 1;").unwrap(), "2\n");
+    }
+
+    #[test]
+    fn test_round_mode() {
+        assert_eq!(
+            run("await_var down = 0; await_var up = 0;
+                 set_round_mode(\"down\"); down = 1 / 3;
+                 set_round_mode(\"up\"); up = 1 / 3;
+                 print down < up;
+                 set_round_mode(\"nearest\");
+                 print 1 / 3 == down or 1 / 3 == up;")
+            .unwrap(),
+            "true\ntrue\n"
+        );
+        assert!(run("set_round_mode(\"sideways\");").is_err());
+    }
+
+    #[test]
+    fn test_busy_loop_with_async_vars() {
+        assert_eq!(
+            run(
+                "fun count(n) { var i = 0; while (i < n) i = i + 1; return i; }
+                 var a = count(3000);
+                 var b = count(3000);
+                 await_var i = 0;
+                 while (i < 3000) { i = i + 1; }
+                 print a + b + i;"
+            )
+            .unwrap(),
+            "9000\n"
+        )
+    }
+
+    #[test]
+    fn test_break_from_nested_block() {
+        assert_eq!(
+            run("var x = \"outer\";
+                 for (var i = 0; i < 10; i = i + 1) {
+                     var x = \"loop\";
+                     { var x = \"inner\"; if (i == 2) { break; } print i; }
+                 }
+                 print x;
+                 await_var n = 0;
+                 repeat (5) { { n = n + 1; if (n == 3) break; } }
+                 print n;")
+            .unwrap(),
+            "0\n1\nouter\n3\n"
+        );
+        assert!(run("break;").is_err());
+        assert!(run("while (true) { fun f() { break; } }").is_err());
+    }
+
+    #[test]
+    fn test_break_releases_block_environments() {
+        let executor = Executor::with_output(&WORKERS, Arc::new(Mutex::new(io::sink())));
+        let mut resolver = Resolver::new(&executor);
+        let before = Arc::strong_count(&executor.environment);
+
+        let statements = Parser::new(
+            &Scanner::new("while (true) { { { await_var x = 1; break; } } }")
+                .scan_tokens()
+                .unwrap(),
+        )
+        .parse()
+        .unwrap();
+        resolver.resolve(Arc::clone(&statements)).unwrap();
+        executor.execute(statements).unwrap();
+
+        assert_eq!(Arc::strong_count(&executor.environment), before);
+    }
+
+    #[test]
+    fn test_continue_outside_loop() {
+        assert!(run("continue;").is_err());
+        assert!(run("while (false) { fun f() { continue; } }").is_err());
+    }
+
+    #[test]
+    fn test_nil_equality() {
+        assert_eq!(
+            run("print nil == nil; print nil == false; print false == nil; print nil != false;")
+                .unwrap(),
+            "true\nfalse\nfalse\ntrue\n"
+        )
+    }
+
+    #[test]
+    fn test_is_nil() {
+        assert_eq!(
+            run("print is_nil(nil); print is_nil(0); print is_nil(false);").unwrap(),
+            "true\nfalse\nfalse\n"
+        )
+    }
+
+    #[test]
+    fn test_fluent_builder() {
+        let source = r#"
+            class Builder {
+                init() { this.x = 0; this.y = 0; }
+                setX(x) { this.x = x; return this; }
+                setY(y) { this.y = y; return this; }
+            }
+
+            await_var a = Builder();
+            await_var b = Builder();
+            print a.setX(1).setY(2).x + a.y;
+            print b.setX(a.setX(5).x).setX(b.x + 1).x;
+        "#;
+
+        assert_eq!(run(source).unwrap(), "3\n6\n")
+    }
+
+    #[test]
+    fn test_environments_are_released() {
+        let executor = Executor::with_output(&WORKERS, Arc::new(Mutex::new(io::sink())));
+        let mut resolver = Resolver::new(&executor);
+
+        let mut run_with = |source: &str| {
+            let statements = Parser::new(&Scanner::new(source).scan_tokens().unwrap())
+                .parse()
+                .unwrap();
+            resolver.resolve(Arc::clone(&statements)).unwrap();
+            executor.execute(statements).unwrap();
+        };
+
+        // `make` keeps the environment it was declared in
+        run_with("fun make() { return lambda(x) { return x + 1; }; }");
+        let before = Arc::strong_count(&executor.environment);
+
+        run_with(
+            "for (await_var i = 0; i < 200; i = i + 1) {
+                await_var f = make();
+                f(i);
+            }",
+        );
+
+        // Every block and call environment points to its parent, so a leaked one would keep a count
+        assert_eq!(Arc::strong_count(&executor.environment), before);
+    }
+
+    type Tokens = Mutex<Vec<Weak<RwLock<Vec<LoxObject>>>>>;
+
+    // Runs the source with a `tracked()` native, and counts the tokens that were dropped
+    fn dropped_tokens(source: &str, tokens: &Tokens, token: NativeFn) -> (usize, usize) {
+        let executor = Executor::with_output(&WORKERS, Arc::new(Mutex::new(io::sink())));
+        executor.define_native("tracked", 0, token);
+        let mut resolver = Resolver::new(&executor);
+
+        let statements = Parser::new(&Scanner::new(source).scan_tokens().unwrap())
+            .parse()
+            .unwrap();
+        resolver.resolve(Arc::clone(&statements)).unwrap();
+        executor.execute(statements).unwrap();
+
+        let tokens = tokens.lock().unwrap();
+        let dropped = tokens.iter().filter(|token| token.upgrade().is_none());
+        (tokens.len(), dropped.count())
+    }
+
+    #[test]
+    fn test_nested_functions_release_their_calls() {
+        static TOKENS: Tokens = Mutex::new(vec![]);
+
+        fn token(_: Vec<LoxObject>) -> LoxResult<LoxObject> {
+            let token = Arc::new(RwLock::new(vec![]));
+            TOKENS.lock().unwrap().push(Arc::downgrade(&token));
+            Ok(LoxObject::List(token))
+        }
+
+        let source = "
+            fun make() {
+                await_var token = tracked();
+                fun keep() { return token; }
+                return len(keep());
+            }
+            fun adder() {
+                await_var token = tracked();
+                fun add(y) { return y + len(token); }
+                return add;
+            }
+            for (await_var i = 0; i < 100; i = i + 1) {
+                make();
+                adder()(i);
+            }";
+
+        assert_eq!(dropped_tokens(source, &TOKENS, token), (200, 200));
+    }
+
+    #[test]
+    fn test_closures_in_loops_are_released() {
+        static TOKENS: Tokens = Mutex::new(vec![]);
+
+        fn token(_: Vec<LoxObject>) -> LoxResult<LoxObject> {
+            let token = Arc::new(RwLock::new(vec![]));
+            TOKENS.lock().unwrap().push(Arc::downgrade(&token));
+            Ok(LoxObject::List(token))
+        }
+
+        let source = "
+            fun keeper() {
+                await_var token = tracked();
+                class Keeper { get() { return token; } }
+                class Child < Keeper {}
+                await_var kept = Child();
+                await_var get = kept.get;
+                return len(get());
+            }
+            fun closes(x) {
+                await_var token = tracked();
+                return lambda() { return x + len(token); };
+            }
+            for (await_var i = 0; i < 100; i = i + 1) {
+                await_var token = tracked();
+                await_var f = lambda(x) { return x + len(token); };
+                fun g(x) { return f(x); }
+                g(i);
+                keeper();
+                closes(i)();
+            }";
+
+        assert_eq!(dropped_tokens(source, &TOKENS, token), (300, 300));
+    }
+
+    #[test]
+    fn test_returned_closures_are_not_memoized() {
+        assert_eq!(
+            run("fun counter(start) {
+                     await_var count = start;
+                     return lambda() { count = count + 1; return count; };
+                 }
+                 await_var a = counter(0);
+                 await_var b = counter(0);
+                 a(); a();
+                 print b();")
+            .unwrap(),
+            "1\n"
+        );
+    }
+
+    #[test]
+    fn test_subclass_methods_see_enclosing_function() {
+        assert_eq!(
+            run("fun make() {
+                     await_var x = 1;
+                     class A { get() { return 1; } }
+                     class B < A { get() { return x + super.get(); } }
+                     return B().get();
+                 }
+                 print make();")
+            .unwrap(),
+            "2\n"
+        );
+    }
+
+    #[test]
+    fn test_number_methods() {
+        assert_eq!(
+            run("print (3.7).floor(); print (-2).abs(); print 2.5.round(); print (1.5).to_string() + \"!\";")
+                .unwrap(),
+            "3\n2\n3\n1.5!\n"
+        );
+        assert!(run("(3).missing();").is_err());
+    }
+
+    #[test]
+    fn test_string_methods() {
+        assert_eq!(
+            run("print \"abc\".upper(); print \"a,b,c\".split(\",\"); print \"hello\".contains(\"ell\"); print \"hello\".len();")
+                .unwrap(),
+            "ABC\n[a, b, c]\ntrue\n5\n"
+        );
+        assert!(run("\"abc\".missing();").is_err());
+    }
+
+    #[test]
+    fn test_arrow_function() {
+        assert_eq!(
+            run("fun square(x) => x * x; fun cube(x) { return x * x * x; } print square(4) + cube(2);")
+                .unwrap(),
+            "24\n"
+        )
+    }
+
+    #[test]
+    fn test_repeat() {
+        assert_eq!(
+            run("repeat (3) { print \"hi\"; } repeat (0) print \"never\";").unwrap(),
+            "hi\nhi\nhi\n"
+        );
+        assert_eq!(
+            run("await_var i = 0; repeat (4) { i = i + 1; if (i == 2) continue; print i; }")
+                .unwrap(),
+            "1\n3\n4\n"
+        );
+        assert!(run("repeat (1.5) {}").is_err());
+        assert!(run("repeat (-1) {}").is_err());
+    }
+
+    #[test]
+    fn test_boolean_rendering() {
+        assert_eq!(
+            run("print true; print \"\" + true; print \"\" + (1 > 2);").unwrap(),
+            "true\ntrue\nfalse\n"
+        );
+        assert_eq!(LoxLiteral::Bool(true).to_string(), "true");
+    }
+
+    #[test]
+    fn test_await_all() {
+        assert_eq!(
+            run("fun count(n) { return lambda() { var i = 0; while (i < n) i = i + 1; return i; }; }
+                 var futures = list();
+                 list_push(futures, spawn(count(300)));
+                 list_push(futures, spawn(count(100)));
+                 list_push(futures, spawn(count(200)));
+                 print await_all(futures);
+                 print list_get(futures, 0);")
+            .unwrap(),
+            "[300, 100, 200]\n#<ready future>\n"
+        );
+
+        let error = run("var futures = list();
+                         list_push(futures, spawn(lambda() { return 1; }));
+                         list_push(futures, spawn(lambda() { return nil + 1; }));
+                         await_all(futures);")
+        .unwrap_err();
+        assert!(error.to_string().contains("nil and number"), "{error}");
+
+        assert!(run("spawn(lambda(x) { return x; });").is_err());
+        assert!(run("await_all(1);").is_err());
+    }
+
+    #[test]
+    fn test_eq_and_hash_methods() {
+        assert_eq!(
+            run("class Point {
+                   init(x, y) { this.x = x; this.y = y; }
+                   __eq__(other) { return this.x == other.x and this.y == other.y; }
+                   __hash__() { return this.x * 100 + this.y; }
+                 }
+                 class Plain { init(x) { this.x = x; } }
+                 var points = set();
+                 set_add(points, Point(1, 2));
+                 set_add(points, Point(1, 2));
+                 print points;
+                 print set_has(points, Point(1, 2));
+                 print Point(1, 2) == Point(1, 2);
+                 print Point(1, 2) != Point(2, 1);
+                 var names = map();
+                 map_set(names, Point(0, 0), \"origin\");
+                 print map_get(names, Point(0, 0));
+                 var plains = set();
+                 set_add(plains, Plain(1));
+                 set_add(plains, Plain(1));
+                 print plains;
+                 print Plain(1) == Plain(1);")
+            .unwrap(),
+            "#<set with 1 values>\ntrue\ntrue\ntrue\norigin\n#<set with 2 values>\nfalse\n"
+        );
+    }
+
+    #[test]
+    fn test_hash_collisions_use_eq() {
+        assert_eq!(
+            run("class P {
+                   init(x) { this.x = x; }
+                   __eq__(other) { return this.x == other.x; }
+                   __hash__() { return 1; }
+                 }
+                 var s = set();
+                 set_add(s, P(1)); set_add(s, P(2)); set_add(s, P(2));
+                 print s;
+                 print set_has(s, P(2)); print set_has(s, P(3));
+                 print set_remove(s, P(1)); print set_has(s, P(1)); print set_has(s, P(2));
+                 var m = map();
+                 map_set(m, P(1), \"one\"); map_set(m, P(2), \"two\"); map_set(m, P(2), \"deux\");
+                 print map_get(m, P(1)); print map_get(m, P(2)); print map_get(m, P(3));
+                 print map_get(m, \"1\"); print has_key(m, \"#<P instance hashed as 1>\");
+                 print len(values(m));")
+            .unwrap(),
+            "#<set with 2 values>\ntrue\nfalse\ntrue\nfalse\ntrue\none\ndeux\nnil\nnil\nfalse\n2\n"
+        );
+    }
+
+    #[test]
+    fn test_assert_throws() {
+        assert!(run("assert_throws(lambda() { return nil + 1; });").is_ok());
+        assert!(run("fun fails() { assert(false); } assert_throws(fails);").is_ok());
+
+        let error = run("assert_throws(lambda() { return 1; });").unwrap_err();
+        assert!(error.to_string().contains("instead of failing"), "{error}");
+
+        assert!(run("assert_throws(1);").is_err());
+    }
+
+    #[test]
+    fn test_trace() {
+        let trace = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let executor = Executor::with_output(&WORKERS, Arc::new(Mutex::new(io::sink())))
+            .with_trace(trace.clone());
+        let mut resolver = Resolver::new(&executor);
+
+        let statements = Parser::new(&Scanner::new("print 1 + 2;").scan_tokens().unwrap())
+            .parse()
+            .unwrap();
+        resolver.resolve(Arc::clone(&statements)).unwrap();
+        executor.execute(statements).unwrap();
+
+        let trace = String::from_utf8(trace.lock().unwrap().clone()).unwrap();
+        let lines = trace.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.first(), Some(&"[trace] print"), "{trace}");
+        assert!(lines.last().unwrap().ends_with("=> 3"), "{trace}");
+    }
+
+    #[test]
+    fn test_chained_comparison() {
+        assert_eq!(
+            run("var x = 5; print 0 < x < 10; print 0 < x <= 5 < 6; print 0 < x > 5;").unwrap(),
+            "true\ntrue\nfalse\n"
+        );
+        assert_eq!(
+            run("await_var calls = 0;
+                 fun middle() { calls = calls + 1; return 5; }
+                 print 0 < middle() < 10;
+                 print calls;
+                 print 9 < middle() < 10;
+                 print calls;")
+            .unwrap(),
+            "true\n1\nfalse\n2\n"
+        );
+        assert!(run("print 1 < 2 < \"3\";").is_err());
+    }
+
+    #[test]
+    fn test_arguments_list() {
+        assert_eq!(
+            run(
+                "fun first(a, b) { print list_get(arguments, 0); print len(arguments); }
+                 first(\"x\", 2);
+                 var f = lambda() { return arguments; };
+                 print f();
+                 fun shadow(arguments) { return arguments; }
+                 print shadow(1);"
+            )
+            .unwrap(),
+            "x\n2\n[]\n1\n"
+        );
+        assert!(run("print arguments;").is_err());
+    }
+
+    #[test]
+    fn test_call_returned_callable() {
+        assert_eq!(
+            run("fun adder(x) { return lambda(y) { return x + y; }; }
+                 print adder(3)(4);
+                 await_var add_ten = adder(10);
+                 print add_ten(1) + add_ten(2);
+                 lambda(x) { print x * 2; }(4);
+                 print lambda(x) { return lambda(y) { return x - y; }; }(5)(2);")
+            .unwrap(),
+            "7\n23\n8\n3\n"
+        )
+    }
+
+    #[test]
+    fn test_is_ready_on_fields_and_entries() {
+        assert_eq!(
+            run("class Job {}
+                 await_var job = Job();
+                 job.done = spawn(lambda() { return 1; });
+                 await_var futures = list();
+                 list_push(futures, job.done);
+                 await_all(futures);
+                 print is_ready job.done;
+                 job.plain = 2;
+                 print is_ready job.plain;
+                 await_var table = map();
+                 map_set(table, \"k\", job.done);
+                 print is_ready map_get(table, \"k\");
+                 print is_ready (1 + 2);")
+            .unwrap(),
+            "true\ntrue\ntrue\ntrue\n"
+        );
+    }
+
+    #[test]
+    fn test_increment_and_decrement() {
+        assert_eq!(
+            run("await_var x = 5;
+                 await_var old = x++;
+                 print old; print x;
+                 await_var new = ++x;
+                 print new; print x;
+                 x--; --x;
+                 print x;
+                 for (var i = 0; i < 2; i++) print i;")
+            .unwrap(),
+            "5\n6\n7\n7\n5\n0\n1\n"
+        );
+        assert!(run("1++;").is_err());
+        assert!(run("class A {} await_var a = A(); a.b = 1; ++a.b;").is_err());
+    }
+
+    #[test]
+    fn test_postfix_increment_keeps_fractional_value() {
+        assert_eq!(
+            run("await_var x = 0.1;
+                 await_var y = x++;
+                 print y == 0.1;
+                 await_var z = x--;
+                 print z == 0.1 + 1;")
+            .unwrap(),
+            "true\ntrue\n"
+        );
+    }
+
+    #[test]
+    fn test_eval_expression_public() {
+        let executor = Executor::new(&WORKERS);
+        let source = "(1 + 2) * 3 - 4 / 2 % 5";
+
+        let expr = Parser::new(&Scanner::new(source).scan_tokens().unwrap())
+            .parse_expression()
+            .unwrap();
+
+        assert_eq!(
+            executor.eval_expression_public(source).unwrap(),
+            executor.eval_expression(&expr).unwrap()
+        );
+        assert!(executor.eval_expression_public("1 +").is_err());
+    }
+
+    #[test]
+    fn test_ordering_dunders() {
+        assert_eq!(
+            run("class Money {
+                   init(cents) { this.cents = cents; }
+                   __lt__(other) { return this.cents < other.cents; }
+                   __gt__(other) { return this.cents > other.cents; }
+                 }
+                 await_var a = Money(500);
+                 await_var b = Money(120);
+                 if (b < a) { await_var t = a; a = b; b = t; }
+                 print a.cents; print b.cents;
+                 print a > b; print Money(1) < Money(2) < Money(3);")
+            .unwrap(),
+            "120\n500\nfalse\ntrue\n"
+        );
+
+        let error = run("class Money {} print Money() <= Money();").unwrap_err();
+        assert!(
+            matches!(error, LoxError::OperandTypeError { .. }),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_sort_by() {
+        assert_eq!(
+            run("await_var numbers = list();
+                 list_push(numbers, 2); list_push(numbers, 3); list_push(numbers, 1);
+                 print sort(numbers);
+                 print sort_by(numbers, lambda(a, b) { return b - a; });
+                 print numbers;")
+            .unwrap(),
+            "[1, 2, 3]\n[3, 2, 1]\n[3, 2, 1]\n"
+        );
+        assert!(run("await_var l = list(); list_push(l, 1); list_push(l, 2);
+                     sort_by(l, lambda(a, b) { return nil; });")
+        .is_err());
+        assert!(run("sort_by(list(), 1);").is_err());
+    }
+
+    #[test]
+    fn test_executor_natives_called_indirectly() {
+        assert_eq!(
+            run("var sorter = sort_by; var copy = clone; var check = assert;
+                 await_var numbers = list(); list_push(numbers, 1); list_push(numbers, 2);
+                 print sorter(numbers, lambda(a, b) { return b - a; });
+                 print copy(numbers);
+                 check(true);")
+            .unwrap(),
+            "[2, 1]\n[2, 1]\n"
+        );
+
+        let error = run("var check = assert; check(false);").unwrap_err();
+        assert!(error.to_string().contains("Assertion failed"), "{error}");
+    }
+
+    #[test]
+    fn test_deep_non_tail_recursion() {
+        assert_eq!(
+            run(
+                "fun depth(n) { if (n == 0) return 0; return 1 + depth(n - 1); }
+                 print depth(10000);"
+            )
+            .unwrap(),
+            "10000\n"
+        );
+    }
+
+    #[test]
+    fn test_shifts() {
+        assert_eq!(
+            run(
+                "print -8 >> 1; print -8 >>> 1; print 8 >>> 1; print 1 << 2 + 1; print 1 < 1 << 1;"
+            )
+            .unwrap(),
+            "-4\n9223372036854775804\n4\n8\ntrue\n"
+        );
+        assert!(run("print 2.5 >> 1;").is_err());
+        assert!(run("print 2 >> -1;").is_err());
+    }
+
+    #[test]
+    fn test_static_fields() {
+        assert_eq!(
+            run("class Point {
+                   class var count = 0;
+                   class var label;
+                   init() { Point.count = Point.count + 1; }
+                 }
+                 class Point3 < Point {}
+                 Point(); Point(); Point3();
+                 print Point.count;
+                 print Point3.count;
+                 print Point.label;")
+            .unwrap(),
+            "3\n3\nnil\n"
+        );
+        assert!(run("class A {} print A.missing;").is_err());
+        assert!(run("class A { class fun f() {} }").is_err());
+    }
+
+    #[test]
+    fn test_lambda_captures_this() {
+        assert_eq!(
+            run("class Counter {
+                   init(n) { this.n = n; }
+                   getter() { return lambda() { return this.n; }; }
+                   bump() { return lambda() { this.n = this.n + 1; }; }
+                 }
+                 await_var a = Counter(1);
+                 await_var b = Counter(10);
+                 await_var get_a = a.getter();
+                 await_var get_b = b.getter();
+                 a.bump()();
+                 print get_a();
+                 print get_b();")
+            .unwrap(),
+            "2\n10\n"
+        )
+    }
+
+    #[test]
+    fn test_functions_see_their_declaring_scope() {
+        assert_eq!(
+            run("await_var g = \"global\";
+                 fun f() { return g; }
+                 { { print f(); } }
+                 fun h() { await_var g = \"local\"; return f(); }
+                 print h();")
+            .unwrap(),
+            "global\nglobal\n"
+        )
+    }
+
+    #[test]
+    fn test_max_time() {
+        for source in [
+            "while (true) {}",
+            "fun forever() { return forever(); } forever();",
+            "fun forever() { while (true) {} } var x = forever(); print x;",
+        ] {
+            let executor = Executor::with_output(&WORKERS, Arc::new(Mutex::new(io::sink())));
+            executor.set_max_time(Duration::from_millis(100));
+            let mut resolver = Resolver::new(&executor);
+
+            let statements = Parser::new(&Scanner::new(source).scan_tokens().unwrap())
+                .parse()
+                .unwrap();
+            resolver.resolve(Arc::clone(&statements)).unwrap();
+
+            let started = Instant::now();
+            let error = executor.execute(statements).unwrap_err();
+
+            assert!(error.to_string().contains("time limit"), "{error}");
+            assert!(started.elapsed() < Duration::from_secs(5));
+        }
+    }
+
+    #[test]
+    fn test_async_initializer_error_names_both_lines() {
+        let error = run("var x = 1 + nil;\nprint \"waiting\";\nprint x;").unwrap_err();
+
+        if let LoxError::RuntimeError { line, msg } = error {
+            assert_eq!(line, Some(3));
+            assert!(
+                msg.contains("Error at 1 - Initializer of 'x' failed"),
+                "{msg}"
+            );
+        } else {
+            panic!("Unexcepted error: {error}")
+        }
+    }
 }
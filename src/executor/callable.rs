@@ -3,36 +3,108 @@ use either::Either;
 use lazy_static::lazy_static;
 
 use crate::{
-    executor::environment::{self},
+    executor::environment::{self, Environment},
     syntax::{Expression, Statement},
     LoxError, LoxResult, Token,
     TokenType::{self, Identifier},
 };
 
-use super::{class::LoxClass, object::LoxObject, Executor};
+use super::{class::LoxClass, object::LoxObject, Executor, Flow};
 
 use std::{
     hash::{Hash, Hasher},
-    sync::Arc,
+    sync::{Arc, Weak},
 };
 
 lazy_static! {
     pub static ref THIS_KEY: u64 = environment::env_hash(format!("{:?}", TokenType::This).as_str());
 }
 
+pub const ARGUMENTS: &str = "arguments";
+
+pub type NativeFn = fn(Vec<LoxObject>) -> LoxResult<LoxObject>;
+
+// Natives that call functions, read the scope or change the interpreter's state
+pub type ExecutorFn = fn(&Executor, &CallSite, Vec<LoxObject>) -> LoxResult<LoxObject>;
+
+// Where a native is called from, calls through a variable or an argument have no source
+#[derive(Default)]
+pub struct CallSite<'a> {
+    pub line: Option<usize>,
+    pub arguments: &'a [Arc<Expression>],
+}
+
+// How a function is shown when printed
+#[derive(Debug, Clone)]
+pub enum FunctionName {
+    Named(String),
+    Lambda,
+    // The class and the method
+    Method(String, String),
+}
+
+pub type Cache = DashMap<Vec<String>, LoxObject, ahash::RandomState>;
+
+// The environment a function was declared in
+#[derive(Debug, Clone)]
+pub enum Closure {
+    Strong(Arc<Environment>),
+    // The copy of a function stored in that same environment only points back weakly, or the
+    // two would keep each other alive. Reading it out of the environment makes it strong again.
+    Weak(Weak<Environment>),
+}
+
+impl Closure {
+    pub fn environment(&self) -> Arc<Environment> {
+        match self {
+            Closure::Strong(environment) => Arc::clone(environment),
+            // Only that environment holds the weak copy, so it's alive whenever the copy is read
+            Closure::Weak(environment) => environment
+                .upgrade()
+                .expect("a function outlived the environment it was stored in"),
+        }
+    }
+
+    pub fn is(&self, environment: &Environment) -> bool {
+        let pointer = match self {
+            Closure::Strong(closure) => Arc::as_ptr(closure),
+            Closure::Weak(closure) => closure.as_ptr(),
+        };
+
+        std::ptr::eq(pointer, environment)
+    }
+}
+
 #[derive(Debug)]
 pub enum LoxCallable {
     Function {
         id: u64,
+        name: FunctionName,
         parameters: Arc<Vec<Token>>,
         body: Arc<Statement>,
-        cache: Option<DashMap<Vec<String>, LoxObject, ahash::RandomState>>,
+        // Shared by the strong and weak copies of the function
+        cache: Option<Arc<Cache>>,
         this: Option<LoxObject>,
         is_initializer: bool,
+        closure: Closure,
     },
     NativeFunction {
         arity: usize,
-        fun: fn(Vec<LoxObject>) -> LoxResult<LoxObject>,
+        fun: NativeFn,
+        // Only reads its arguments, so calls to it may run in any order
+        pure: bool,
+        // Takes `arity` arguments or more
+        variadic: bool,
+    },
+    ExecutorFunction {
+        arity: usize,
+        fun: ExecutorFn,
+    },
+    // A native called as a method, the receiver is passed as the first argument
+    NativeMethod {
+        arity: usize,
+        fun: NativeFn,
+        receiver: LoxObject,
     },
     Class {
         class: Arc<LoxClass>,
@@ -40,64 +112,94 @@ pub enum LoxCallable {
 }
 
 impl LoxCallable {
-    pub fn new(parameters: Arc<Vec<Token>>, body: Arc<Statement>) -> Self {
+    pub fn new(
+        name: FunctionName,
+        parameters: Arc<Vec<Token>>,
+        body: Arc<Statement>,
+        id: u64,
+        closure: Arc<Environment>,
+    ) -> Self {
         Self::Function {
-            id: rand::random(),
+            id,
+            name,
             parameters,
             body,
-            cache: Some(DashMap::with_hasher(ahash::RandomState::new())),
+            cache: Some(Arc::new(DashMap::with_hasher(ahash::RandomState::new()))),
             this: None,
             is_initializer: false,
+            closure: Closure::Strong(closure),
         }
     }
 
     pub fn new_with_id(
+        name: FunctionName,
         parameters: Arc<Vec<Token>>,
         body: Arc<Statement>,
         id: u64,
         this: Option<LoxObject>,
         is_initializer: bool,
+        closure: Arc<Environment>,
     ) -> Self {
         Self::Function {
             id,
+            name,
             parameters,
             body,
-            cache: Some(DashMap::with_hasher(ahash::RandomState::new())),
+            cache: Some(Arc::new(DashMap::with_hasher(ahash::RandomState::new()))),
             this,
             is_initializer,
+            closure: Closure::Strong(closure),
         }
     }
 
     pub fn new_method(
+        name: FunctionName,
         parameters: Arc<Vec<Token>>,
         body: Arc<Statement>,
         is_initializer: bool,
+        id: u64,
+        closure: Arc<Environment>,
     ) -> Self {
         Self::Function {
-            id: rand::random(),
+            id,
+            name,
             parameters,
             body,
             cache: None,
             this: None,
             is_initializer,
+            // Their class keeps the environment alive
+            closure: Closure::Weak(Arc::downgrade(&closure)),
         }
     }
 
     pub fn bind(&self, this: &LoxObject) -> Self {
-        if let LoxCallable::Function {
-            parameters,
-            body,
-            is_initializer,
-            ..
-        } = self
+        if let (
+            LoxCallable::Function {
+                id,
+                name,
+                parameters,
+                body,
+                is_initializer,
+                closure,
+                ..
+            },
+            LoxObject::Instance(instance_id, ..),
+        ) = (self, this)
         {
+            // A method bound to the same instance always gets the same id
+            let mut hasher = ahash::AHasher::default();
+            (id, instance_id).hash(&mut hasher);
+
             LoxCallable::Function {
-                id: rand::random(),
+                id: hasher.finish(),
+                name: name.clone(),
                 parameters: Arc::clone(parameters),
                 body: Arc::clone(body),
                 cache: None,
                 this: Some(LoxObject::from(this)),
                 is_initializer: *is_initializer,
+                closure: Closure::Strong(closure.environment()),
             }
         } else {
             unreachable!()
@@ -109,7 +211,9 @@ impl LoxCallable {
 
         match self {
             Function { parameters, .. } => parameters.len(),
-            NativeFunction { arity, .. } => *arity,
+            NativeFunction { arity, .. }
+            | ExecutorFunction { arity, .. }
+            | NativeMethod { arity, .. } => *arity,
             Class { class } => match class.find_method("init") {
                 None => 0,
                 Some(callable) => callable.arity(),
@@ -117,131 +221,255 @@ impl LoxCallable {
         }
     }
 
-    pub fn call(&self, executor: &Executor, mut arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
-        use LoxCallable::*;
-
-        if arguments.len() != self.arity() {
-            return Err(LoxError::RuntimeError {
-                line: None,
-                msg: "Wrong number of arguments".into(),
-            });
+    // The copy of a function or class to store in the environment it was declared in
+    pub fn weakened(&self, environment: &Environment) -> Option<Self> {
+        match self {
+            LoxCallable::Function {
+                closure: closure @ Closure::Strong(strong),
+                this,
+                ..
+            } if closure.is(environment) => Some(
+                self.with_closure(
+                    Closure::Weak(Arc::downgrade(strong)),
+                    this.as_ref()
+                        .map(|this| LoxObject::from(this).weakened(environment)),
+                ),
+            ),
+            LoxCallable::Class { class } => Some(LoxCallable::Class {
+                class: Arc::new(class.weakened(environment)?),
+            }),
+            _ => None,
         }
+    }
 
+    pub fn strengthened(&self) -> Option<Self> {
         match self {
-            Function {
+            LoxCallable::Function {
+                closure: closure @ Closure::Weak(..),
+                this,
+                ..
+            } => Some(self.with_closure(
+                Closure::Strong(closure.environment()),
+                this.as_ref().map(LoxObject::from),
+            )),
+            LoxCallable::Class { class } => Some(LoxCallable::Class {
+                class: Arc::new(class.strengthened()?),
+            }),
+            _ => None,
+        }
+    }
+
+    fn with_closure(&self, closure: Closure, this: Option<LoxObject>) -> Self {
+        let LoxCallable::Function {
+            id,
+            name,
+            parameters,
+            body,
+            cache,
+            is_initializer,
+            ..
+        } = self
+        else {
+            unreachable!()
+        };
+
+        LoxCallable::Function {
+            id: *id,
+            name: name.clone(),
+            parameters: Arc::clone(parameters),
+            body: Arc::clone(body),
+            cache: cache.as_ref().map(Arc::clone),
+            this,
+            is_initializer: *is_initializer,
+            closure,
+        }
+    }
+
+    pub fn is_variadic(&self) -> bool {
+        matches!(self, LoxCallable::NativeFunction { variadic: true, .. })
+    }
+
+    pub fn is_pure(&self) -> bool {
+        matches!(self, LoxCallable::NativeFunction { pure: true, .. })
+    }
+
+    // A call in tail position, even inside an `if` or a block, runs in this loop instead of
+    // nesting. Calls to other functions are run here too, so mutual recursion stays flat.
+    fn call_function(
+        &self,
+        caller: &Executor,
+        mut arguments: Vec<LoxObject>,
+    ) -> LoxResult<LoxObject> {
+        let scope = |function: &LoxCallable| {
+            let LoxCallable::Function { closure, this, .. } = function else {
+                unreachable!()
+            };
+            let mut environment = closure.environment();
+
+            // `this` lives in its own environment between the class and the method body
+            if let Some(this) = this {
+                let with_this = Environment::new_with_parent(environment);
+                with_this.values.insert(
+                    *THIS_KEY,
+                    environment::PackagedObject::Ready(Ok(LoxObject::from(this))),
+                );
+                environment = Arc::new(with_this);
+            }
+
+            caller.with_environment(Arc::new(Environment::new_with_parent(environment)))
+        };
+
+        let mut tail_callee: Option<Arc<LoxCallable>> = None;
+        let mut executor = scope(self);
+
+        loop {
+            let function = tail_callee.as_deref().unwrap_or(self);
+            let LoxCallable::Function {
                 parameters,
                 body,
                 cache,
                 this,
                 is_initializer,
                 ..
-            } => {
-                if let Some(obj) = this.as_ref() {
-                    executor
-                        .environment
-                        .enclosing
-                        .as_ref()
-                        .unwrap()
-                        .values
-                        .insert(
-                            *THIS_KEY,
-                            environment::PackagedObject::Ready(Ok(LoxObject::from(obj))),
-                        );
+            } = function
+            else {
+                unreachable!()
+            };
+
+            executor.check_interrupt(None)?;
+
+            if let Some(cache) = cache {
+                if function.arity() != 0 {
+                    let cache_key: Vec<String> = arguments.iter().map(|i| i.to_string()).collect();
+                    if let Some(early) = cache.get(&cache_key) {
+                        return Ok(LoxObject::from(early.value()));
+                    };
                 }
+            }
 
-                loop {
-                    if let Some(cache) = cache {
-                        if self.arity() != 0 {
-                            let cache_key: Vec<String> =
-                                arguments.iter().map(|i| i.to_string()).collect();
-                            if let Some(early) = cache.get(&cache_key) {
-                                return Ok(LoxObject::from(early.value()));
-                            };
-                        }
-                    }
+            environment::put_immediately(
+                Arc::clone(&executor.environment),
+                &executor,
+                ARGUMENTS,
+                Either::Right(LoxObject::from(arguments.clone())),
+            );
+
+            for (index, param) in parameters.iter().enumerate() {
+                if let Identifier(name) = &param.kind {
+                    environment::put_immediately(
+                        Arc::clone(&executor.environment),
+                        &executor,
+                        name,
+                        Either::Right(arguments.get(index).unwrap().into()),
+                    )
+                }
+            }
 
-                    for (index, param) in parameters.iter().enumerate() {
-                        if let Identifier(name) = &param.kind {
-                            environment::put_immediately(
-                                Arc::clone(&executor.environment),
-                                Arc::clone(&executor.locals),
-                                name,
-                                Either::Right(arguments.get(index).unwrap().into()),
-                            )
+            return match executor.eval_statement(Arc::clone(body)) {
+                Ok(Flow::Next | Flow::Continue | Flow::Break) => Ok(LoxObject::Nil),
+                // The return site's scope, even a block or loop body, lives on in the flow
+                Ok(Flow::Return(inner_env, val)) => match val {
+                    None => Ok(LoxObject::Nil),
+                    Some(expr) => {
+                        let sub_executor = executor.with_environment(Arc::clone(&inner_env));
+
+                        let mut tail = expr.as_ref();
+                        while let Expression::Grouping(inner) = tail {
+                            tail = inner;
                         }
-                    }
 
-                    let result = match executor.eval_statement(Arc::clone(body)) {
-                        Ok(()) => return Ok(LoxObject::Nil),
-                        Err(LoxError::Return(inner_env, val)) => match val {
-                            None => Ok(LoxObject::Nil),
-                            Some(expr) => {
-                                let sub_executor = Executor {
-                                    environment: Arc::clone(&inner_env),
-                                    locals: Arc::clone(&executor.locals),
-                                    workers: executor.workers,
-                                };
-
-                                let val =
-                                // This seems like a mess. Everywhere is filled with eval_expression!
-                                if let Expression::Call(callee, _paren, uneval_inner_arguments) = expr.as_ref() {
-                                    let callee = sub_executor.eval_expression(callee)?;
-
-                                    if let LoxObject::Callable(callable) = callee {
-                                        // Tail call
-                                        if callable.as_ref() == self {
-                                            arguments = {
-                                                    let mut res = vec![];
-
-                                                    for arg in uneval_inner_arguments {
-                                                        res.push(sub_executor.eval_expression(arg)?);
-                                                    }
-
-                                                    res
-                                                };
-
-                                            continue
-                                        } else {
-                                            // Not a tail call
-                                            sub_executor.eval_expression(&expr)?
-                                        }
-                                    } else {
-                                        // Not callable
-                                        sub_executor.eval_expression(&expr)?
+                        if let Expression::Call(callee, _paren, uneval_arguments) = tail {
+                            if let LoxObject::Callable(callable) =
+                                sub_executor.eval_expression(callee)?
+                            {
+                                if callable.is_tail_callable(uneval_arguments.len()) {
+                                    let mut res = vec![];
+
+                                    for arg in uneval_arguments {
+                                        res.push(sub_executor.eval_expression(arg)?);
                                     }
-                                } else {
-                                    // Not a call expression
-                                    sub_executor.eval_expression(&expr)?
-                                };
-
-                                if self.arity() != 0 {
-                                    cache.as_ref().and_then(|cache| {
-                                        cache.insert(
-                                            arguments.iter().map(|i| i.to_string()).collect(),
-                                            LoxObject::from(&val),
-                                        )
-                                    });
-                                }
 
-                                Ok(val)
-                            }
-                        },
-                        error => {
-                            if *is_initializer {
-                                return Ok(LoxObject::from(this.as_ref().unwrap()));
-                            } else {
-                                error.map(|_| LoxObject::Nil)
+                                    arguments = res;
+
+                                    // Calls to itself keep reusing the same environment
+                                    if callable.as_ref() != function {
+                                        executor = scope(&callable);
+                                        tail_callee = Some(callable);
+                                    }
+
+                                    continue;
+                                }
                             }
                         }
-                    };
 
-                    return result;
+                        let val = sub_executor.eval_expression(tail)?;
+
+                        // A returned function captures this call, every call gets its own
+                        if function.arity() != 0 && !matches!(val, LoxObject::Callable(..)) {
+                            cache.as_ref().and_then(|cache| {
+                                cache.insert(
+                                    arguments.iter().map(|i| i.to_string()).collect(),
+                                    LoxObject::from(&val),
+                                )
+                            });
+                        }
+
+                        Ok(val)
+                    }
+                },
+                error => {
+                    if *is_initializer {
+                        Ok(LoxObject::from(this.as_ref().unwrap()))
+                    } else {
+                        error.map(|_| LoxObject::Nil)
+                    }
                 }
+            };
+        }
+    }
+
+    // Natives, classes and initializers are called the usual way
+    fn is_tail_callable(&self, arguments: usize) -> bool {
+        matches!(
+            self,
+            LoxCallable::Function {
+                is_initializer: false,
+                ..
             }
+        ) && self.arity() == arguments
+    }
+
+    pub fn call(&self, executor: &Executor, arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+        self.call_at(executor, &CallSite::default(), arguments)
+    }
+
+    pub fn call_at(
+        &self,
+        executor: &Executor,
+        site: &CallSite,
+        mut arguments: Vec<LoxObject>,
+    ) -> LoxResult<LoxObject> {
+        use LoxCallable::*;
+
+        let expected = self.arity();
+        if arguments.len() < expected || (arguments.len() > expected && !self.is_variadic()) {
+            return Err(LoxError::RuntimeError {
+                line: None,
+                msg: "Wrong number of arguments".into(),
+            });
+        }
+
+        match self {
+            Function { .. } => self.call_function(executor, arguments),
             NativeFunction { fun, .. } => fun(arguments),
+            ExecutorFunction { fun, .. } => fun(executor, site, arguments),
+            NativeMethod { fun, receiver, .. } => {
+                arguments.insert(0, LoxObject::from(receiver));
+                fun(arguments)
+            }
             Class { class } => {
                 let instance = LoxObject::Instance(
-                    rand::random(),
+                    executor.next_id(),
                     Arc::clone(class),
                     Arc::new(DashMap::with_hasher(ahash::RandomState::new())),
                 );
@@ -262,22 +490,46 @@ impl From<&LoxCallable> for LoxCallable {
         match callable {
             Function {
                 id,
+                name,
                 parameters,
                 body,
                 cache: _,
                 this,
                 is_initializer,
+                closure,
             } => LoxCallable::new_with_id(
+                name.clone(),
                 Arc::clone(parameters),
                 Arc::clone(body),
                 *id,
                 this.as_ref().map(LoxObject::from),
                 *is_initializer,
+                closure.environment(),
             ),
-            NativeFunction { arity, fun } => LoxCallable::NativeFunction {
+            NativeFunction {
+                arity,
+                fun,
+                pure,
+                variadic,
+            } => LoxCallable::NativeFunction {
+                arity: *arity,
+                fun: *fun,
+                pure: *pure,
+                variadic: *variadic,
+            },
+            ExecutorFunction { arity, fun } => LoxCallable::ExecutorFunction {
                 arity: *arity,
                 fun: *fun,
             },
+            NativeMethod {
+                arity,
+                fun,
+                receiver,
+            } => LoxCallable::NativeMethod {
+                arity: *arity,
+                fun: *fun,
+                receiver: LoxObject::from(receiver),
+            },
             Class { class } => Class {
                 class: Arc::clone(class),
             },
@@ -293,6 +545,11 @@ impl Hash for LoxCallable {
 
         match self {
             NativeFunction { fun, .. } => fun.hash(state),
+            ExecutorFunction { fun, .. } => fun.hash(state),
+            NativeMethod { fun, receiver, .. } => {
+                fun.hash(state);
+                receiver.to_string().hash(state)
+            }
             Function { id, .. } => id.hash(state),
             Class { class } => class.name.hash(state),
         }
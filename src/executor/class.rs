@@ -1,14 +1,19 @@
 use std::{fmt::Display, sync::Arc};
 
 use ahash::AHashMap;
+use dashmap::DashMap;
 
-use super::LoxCallable;
+use super::{callable::Closure, Environment, LoxCallable, LoxObject};
 
 #[derive(Debug)]
 pub struct LoxClass {
     pub name: String,
     pub superclass: Option<Arc<Self>>,
-    pub methods: AHashMap<String, LoxCallable>,
+    pub methods: Arc<AHashMap<String, LoxCallable>>,
+    // Declared with `class var`, shared by every instance
+    pub static_fields: Arc<DashMap<String, LoxObject>>,
+    // Methods only point weakly to the environment they were declared in, the class keeps it
+    pub closure: Option<Closure>,
 }
 
 impl LoxClass {
@@ -16,11 +21,65 @@ impl LoxClass {
         name: String,
         superclass: Option<Arc<Self>>,
         methods: AHashMap<String, LoxCallable>,
+        closure: Option<Arc<Environment>>,
     ) -> Self {
         Self {
             name,
             superclass,
-            methods,
+            methods: Arc::new(methods),
+            static_fields: Arc::new(DashMap::new()),
+            closure: closure.map(Closure::Strong),
+        }
+    }
+
+    // The copy to store in the environment the class was declared in, like for functions.
+    // A superclass declared there too would close the cycle, so it's weakened as well.
+    pub fn weakened(&self, environment: &Environment) -> Option<Self> {
+        match &self.closure {
+            Some(closure @ Closure::Strong(strong)) if closure.is(environment) => {
+                let superclass = self.superclass.as_ref().map(|superclass| {
+                    superclass
+                        .weakened(environment)
+                        .map_or_else(|| Arc::clone(superclass), Arc::new)
+                });
+
+                Some(self.with_closure(superclass, Closure::Weak(Arc::downgrade(strong))))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn strengthened(&self) -> Option<Self> {
+        match &self.closure {
+            Some(closure @ Closure::Weak(..)) => {
+                let superclass = self.superclass.as_ref().map(|superclass| {
+                    superclass
+                        .strengthened()
+                        .map_or_else(|| Arc::clone(superclass), Arc::new)
+                });
+
+                Some(self.with_closure(superclass, Closure::Strong(closure.environment())))
+            }
+            _ => None,
+        }
+    }
+
+    fn with_closure(&self, superclass: Option<Arc<Self>>, closure: Closure) -> Self {
+        Self {
+            name: self.name.clone(),
+            superclass,
+            methods: Arc::clone(&self.methods),
+            static_fields: Arc::clone(&self.static_fields),
+            closure: Some(closure),
+        }
+    }
+
+    // Looks through superclasses too, so subclasses share the counters of their parents
+    pub fn find_static_owner(&self, field_name: &str) -> Option<&Self> {
+        if self.static_fields.contains_key(field_name) {
+            Some(self)
+        } else {
+            self.superclass.as_ref()?.find_static_owner(field_name)
         }
     }
 
@@ -1,19 +1,22 @@
 use dashmap::mapref::one::Ref;
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use either::Either::{self, Left, Right};
 use std::hash::Hasher;
 use std::sync::Mutex;
 use std::sync::{Arc, Condvar};
+use threadpool::ThreadPool;
 
 use super::object::LoxObject;
-use super::{Executor, LocalsMap};
+use super::{Executor, LoxCallable};
 use crate::syntax::Expression;
-use crate::{LoxResult, WORKERS};
+use crate::{LoxError, LoxResult};
 
 #[derive(Debug)]
 pub enum PackagedObject {
     Pending(Mutex<bool>, Condvar),
     Ready(LoxResult<LoxObject>),
+    // Declared with `var x;` and not assigned yet
+    Uninitialized,
 }
 
 impl PackagedObject {
@@ -22,31 +25,39 @@ impl PackagedObject {
             Self::Pending(mtx, cvar) => {
                 let res = mtx.lock().unwrap();
 
-                let _ = cvar.wait_while(res, |pending| !*pending);
+                drop(cvar.wait_while(res, |pending| !*pending));
                 self.wait_for_value()
             }
             Self::Ready(val) => val,
+            // Only used for `this`, which is always initialized
+            Self::Uninitialized => unreachable!(),
         }
     }
 
     pub fn is_ready(&self) -> bool {
         match self {
             Self::Pending(..) => false,
-            Self::Ready(..) => true,
+            Self::Ready(..) | Self::Uninitialized => true,
         }
     }
 }
 
 #[derive(Debug)]
 pub struct Environment {
+    // Environments point to their parents, functions and classes keep the environment they
+    // were declared in, and instances keep their class. Stored in that same environment, they
+    // point back weakly, see `own`. Inside a list, map or field they still keep it alive.
     pub enclosing: Option<Arc<Environment>>,
     pub values: DashMap<u64, PackagedObject, ahash::RandomState>,
+    // Keys of natives, which can be shadowed in inner scopes but not reassigned here
+    pub natives: DashSet<u64, ahash::RandomState>,
 }
 
 impl Default for Environment {
     fn default() -> Self {
         Self {
             values: DashMap::with_hasher(ahash::RandomState::new()),
+            natives: DashSet::with_hasher(ahash::RandomState::new()),
             enclosing: None,
         }
     }
@@ -57,6 +68,7 @@ impl Environment {
         Self {
             enclosing: Some(enclosing),
             values: DashMap::with_hasher(ahash::RandomState::new()),
+            natives: DashSet::with_hasher(ahash::RandomState::new()),
         }
     }
 
@@ -83,13 +95,59 @@ impl Environment {
     }
 
     pub fn assign_at(&self, distance: usize, key: u64, value: LoxObject) -> Option<()> {
-        self.ancestor(distance)?
+        let environment = self.ancestor(distance)?;
+        environment
             .values
-            .insert(key, PackagedObject::Ready(Ok(value)));
+            .insert(key, PackagedObject::Ready(Ok(environment.own(value))));
 
         Some(())
     }
 
+    // A value about to be stored here, functions declared here only keep a weak reference to
+    // it. The global scope lives as long as the program anyway.
+    pub fn own(&self, value: LoxObject) -> LoxObject {
+        if self.enclosing.is_none() {
+            return value;
+        }
+
+        value.weakened(self)
+    }
+
+    pub fn root(&self) -> &Self {
+        match &self.enclosing {
+            Some(env) => env.root(),
+            None => self,
+        }
+    }
+
+    // Names are only stored as hashes, so those are listed instead
+    pub fn describe(&self) -> String {
+        let mut result = String::new();
+        let mut environment = Some(self);
+        let mut depth = 0;
+
+        while let Some(env) = environment {
+            let mut values: Vec<(u64, bool)> = env
+                .values
+                .iter()
+                .map(|pair| (*pair.key(), pair.value().is_ready()))
+                .collect();
+            values.sort_unstable();
+
+            result.push_str(&format!("scope {depth}: {} variables\n", values.len()));
+
+            for (key, ready) in values {
+                let state = if ready { "Ready" } else { "Pending" };
+                result.push_str(&format!("  {key:016x} {state}\n"));
+            }
+
+            environment = env.enclosing.as_deref();
+            depth += 1;
+        }
+
+        result
+    }
+
     pub fn ancestor(&self, distance: usize) -> Option<&Self> {
         let mut environment = self;
 
@@ -111,6 +169,7 @@ macro_rules! create_sub_environment {
                 Environment {
                     enclosing: Some(Arc::clone(&$env.clone())),
                     values: new_map,
+                    natives: DashSet::with_hasher(ahash::RandomState::new()),
                 }
                 .into()
             }
@@ -119,8 +178,15 @@ macro_rules! create_sub_environment {
     };
 }
 
-pub fn put(environment: Arc<Environment>, locals: LocalsMap, name: &str, expr: Arc<Expression>) {
+pub fn put(
+    environment: Arc<Environment>,
+    executor: &Executor,
+    name: &str,
+    line: usize,
+    expr: Arc<Expression>,
+) {
     let key = env_hash(name);
+    let name = name.to_owned();
 
     // To avoid deadlock, we have to remove the old value
     let existing_key = environment.values.remove(&key);
@@ -133,27 +199,140 @@ pub fn put(environment: Arc<Environment>, locals: LocalsMap, name: &str, expr: A
 
     let sub_environment = create_sub_environment!(existing_key, environment);
 
-    let executor = Executor {
-        workers: &WORKERS,
-        environment: Arc::clone(&sub_environment),
-        locals: Arc::clone(&locals),
-    };
+    let workers = executor.workers;
+    let executor = executor.with_environment(Arc::clone(&sub_environment));
 
-    WORKERS.execute(move || {
-        let value = executor.eval_expression(&expr);
+    let task = move || {
+        let value = executor
+            .eval_expression(&expr)
+            .map_err(|e| LoxError::RuntimeError {
+                line: Some(line),
+                msg: format!("Initializer of '{name}' failed - {e}"),
+            });
 
         if let PackagedObject::Pending(mtx, cdv) = sub_environment.get(&key).unwrap().value() {
             *mtx.lock().unwrap() = true;
             cdv.notify_all();
         }
 
+        let value = value.map(|value| environment.own(value));
         environment.values.insert(key, PackagedObject::Ready(value));
+    };
+
+    schedule(workers, task)
+}
+
+// When every worker is busy, they may all be waiting on this value.
+// Evaluating it here avoids starving the pool.
+fn schedule(workers: &ThreadPool, task: impl FnOnce() + Send + 'static) {
+    if workers.active_count() + workers.queued_count() >= workers.max_count() {
+        task()
+    } else {
+        workers.execute(task)
+    }
+}
+
+// Futures are environments holding a single value under this key
+const FUTURE_KEY: u64 = 0;
+
+pub fn spawn(executor: &Executor, callable: Arc<LoxCallable>) -> Arc<Environment> {
+    let future = Arc::new(Environment::default());
+
+    future.values.insert(
+        FUTURE_KEY,
+        PackagedObject::Pending(Mutex::new(false), Condvar::new()),
+    );
+
+    let workers = executor.workers;
+    let executor = executor.with_environment(Arc::new(Environment::new_with_parent(Arc::clone(
+        &executor.environment,
+    ))));
+    let environment = Arc::clone(&future);
+
+    schedule(workers, move || {
+        let value = callable.call(&executor, vec![]);
+        settle(&environment, FUTURE_KEY, value);
     });
+
+    future
+}
+
+// Evaluates every expression but the last on the workers, the last one here.
+// Results are read from left to right, so the leftmost error is reported.
+pub fn evaluate_parallel(
+    executor: &Executor,
+    expressions: &[Arc<Expression>],
+) -> LoxResult<Vec<LoxObject>> {
+    let Some((last, rest)) = expressions.split_last() else {
+        return Ok(vec![]);
+    };
+    let results = Arc::new(Environment::default());
+
+    for (key, expr) in (0..).zip(rest) {
+        results.values.insert(
+            key,
+            PackagedObject::Pending(Mutex::new(false), Condvar::new()),
+        );
+
+        let executor = executor.clone();
+        let expr = Arc::clone(expr);
+        let results = Arc::clone(&results);
+
+        schedule(executor.workers, move || {
+            let value = executor.eval_expression(&expr);
+            settle(&results, key, value);
+        });
+    }
+
+    let last = executor.eval_expression(last);
+    let mut values = vec![];
+
+    for key in 0..rest.len() as u64 {
+        values.push(wait_value(&results, key).unwrap()?);
+    }
+
+    values.push(last?);
+    Ok(values)
+}
+
+fn settle(environment: &Environment, key: u64, value: LoxResult<LoxObject>) {
+    if let PackagedObject::Pending(mtx, cdv) = environment.get(&key).unwrap().value() {
+        *mtx.lock().unwrap() = true;
+        cdv.notify_all();
+    }
+
+    environment.values.insert(key, PackagedObject::Ready(value));
+}
+
+pub fn wait_future(future: &Environment) -> LoxResult<LoxObject> {
+    wait_value(future, FUTURE_KEY).unwrap()
+}
+
+// Waits for a value of this environment only, None when it isn't defined here
+pub fn wait_value(environment: &Environment, key: u64) -> Option<LoxResult<LoxObject>> {
+    loop {
+        let pair = environment.values.get(&key)?;
+
+        match pair.value() {
+            PackagedObject::Pending(mtx, cvar) => {
+                let lock = mtx.lock().unwrap();
+
+                drop(cvar.wait_while(lock, |pending| !*pending));
+            }
+            PackagedObject::Ready(Ok(value)) => return Some(Ok(LoxObject::from(value))),
+            PackagedObject::Ready(Err(e)) => return Some(Err(e.clone())),
+            PackagedObject::Uninitialized => return None,
+        }
+    }
+}
+
+pub fn is_future_ready(future: &Environment) -> bool {
+    future.get(&FUTURE_KEY).unwrap().is_ready()
 }
 
 pub fn put_immediately(
     environment: Arc<Environment>,
-    locals: LocalsMap,
+    executor: &Executor,
     name: &str,
     expr_or_obj: Either<&Expression, LoxObject>,
 ) {
@@ -162,21 +341,63 @@ pub fn put_immediately(
     let existing_key = environment.values.remove(&key);
 
     let sub_environment = create_sub_environment!(existing_key, environment);
-    let sub_executor = Executor {
-        environment: sub_environment,
-        locals,
-        workers: &WORKERS,
-    };
+    let sub_executor = executor.with_environment(sub_environment);
 
     Arc::clone(&environment).values.insert(
         env_hash(name),
-        PackagedObject::Ready(match expr_or_obj {
-            Left(expr) => sub_executor.eval_expression(expr),
-            Right(obj) => Ok(obj),
-        }),
+        PackagedObject::Ready(
+            match expr_or_obj {
+                Left(expr) => sub_executor.eval_expression(expr),
+                Right(obj) => Ok(obj),
+            }
+            .map(|value| environment.own(value)),
+        ),
     );
 }
 
+pub fn put_native(
+    environment: Arc<Environment>,
+    executor: &Executor,
+    name: &str,
+    native: LoxCallable,
+) {
+    environment.natives.insert(env_hash(name));
+    put_immediately(environment, executor, name, Right(LoxObject::from(native)));
+}
+
+// Environments of finished blocks, kept to spare their maps from being allocated again
+pub type ScopePool = Arc<Mutex<Vec<Arc<Environment>>>>;
+
+const MAX_POOLED_SCOPES: usize = 64;
+
+pub fn take_scope(pool: &ScopePool, enclosing: Arc<Environment>) -> Arc<Environment> {
+    match pool.lock().unwrap().pop() {
+        Some(mut scope) => {
+            // Only the pool held it
+            Arc::get_mut(&mut scope).unwrap().enclosing = Some(enclosing);
+            scope
+        }
+        None => Arc::new(Environment::new_with_parent(enclosing)),
+    }
+}
+
+// Closures, futures and pending initializers keep their environment alive, it is reused
+// only when nothing else points to it anymore.
+pub fn recycle_scope(pool: &ScopePool, mut scope: Arc<Environment>) {
+    let Some(environment) = Arc::get_mut(&mut scope) else {
+        return;
+    };
+
+    environment.values.clear();
+    environment.natives.clear();
+    environment.enclosing = None;
+
+    let mut pool = pool.lock().unwrap();
+    if pool.len() < MAX_POOLED_SCOPES {
+        pool.push(scope);
+    }
+}
+
 pub fn env_hash(name: &str) -> u64 {
     let mut hasher = ahash::AHasher::default();
     hasher.write(name.as_bytes());
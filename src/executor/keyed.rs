@@ -0,0 +1,137 @@
+use dashmap::DashMap;
+
+use super::{Executor, LoxObject};
+use crate::LoxResult;
+
+// The members of a set or the entries of a map. Values are kept by their printed form, so 1
+// and "1" are the same key. Instances with `__hash__` go in a bucket per hash instead, and
+// `__eq__` tells the instances in a bucket apart.
+#[derive(Debug)]
+pub struct Keyed<V> {
+    pub plain: DashMap<String, V, ahash::RandomState>,
+    pub hashed: DashMap<String, Vec<(LoxObject, V)>, ahash::RandomState>,
+}
+
+impl<V> Default for Keyed<V> {
+    fn default() -> Self {
+        Self {
+            plain: DashMap::with_hasher(ahash::RandomState::new()),
+            hashed: DashMap::with_hasher(ahash::RandomState::new()),
+        }
+    }
+}
+
+impl<V: Clone> Keyed<V> {
+    pub fn len(&self) -> usize {
+        self.plain.len() + self.hashed.iter().map(|bucket| bucket.len()).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Same keys, with values `eq` finds the same. Hashed keys must be the same instances.
+    pub fn same_as(&self, other: &Keyed<V>, mut eq: impl FnMut(&V, &V) -> bool) -> bool {
+        self.len() == other.len()
+            && self.plain.iter().all(|entry| {
+                other
+                    .plain
+                    .get(entry.key())
+                    .is_some_and(|other| eq(entry.value(), other.value()))
+            })
+            && self.hashed.iter().all(|bucket| {
+                other.hashed.get(bucket.key()).is_some_and(|other| {
+                    bucket.iter().all(|(key, value)| {
+                        other.iter().any(|(other_key, other_value)| {
+                            same(key, other_key) && eq(value, other_value)
+                        })
+                    })
+                })
+            })
+    }
+
+    // Plain keys are given back as strings, hashed ones as the instances themselves
+    pub fn entries(&self) -> Vec<(LoxObject, V)> {
+        let plain = self
+            .plain
+            .iter()
+            .map(|entry| (LoxObject::from(entry.key().as_str()), entry.value().clone()));
+        let hashed = self
+            .hashed
+            .iter()
+            .flat_map(|bucket| bucket.value().clone())
+            .collect::<Vec<_>>();
+
+        plain.chain(hashed).collect()
+    }
+
+    pub fn get(&self, executor: &Executor, key: &LoxObject) -> LoxResult<Option<V>> {
+        match executor.hash_key(key) {
+            None => Ok(self.plain.get(&key.to_string()).map(|v| v.value().clone())),
+            Some(hash) => Ok(self.find(executor, &hash?, key)?.map(|(_, value)| value)),
+        }
+    }
+
+    // Gives back the value that was replaced
+    pub fn insert(&self, executor: &Executor, key: &LoxObject, value: V) -> LoxResult<Option<V>> {
+        let hash = match executor.hash_key(key) {
+            None => return Ok(self.plain.insert(key.to_string(), value)),
+            Some(hash) => hash?,
+        };
+
+        let found = self.find(executor, &hash, key)?;
+        let mut bucket = self.hashed.entry(hash).or_default();
+
+        match found.and_then(|(found, _)| bucket.iter_mut().find(|(k, _)| same(k, &found))) {
+            Some((_, old)) => Ok(Some(std::mem::replace(old, value))),
+            None => {
+                bucket.push((LoxObject::from(key), value));
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn remove(&self, executor: &Executor, key: &LoxObject) -> LoxResult<Option<V>> {
+        let hash = match executor.hash_key(key) {
+            None => return Ok(self.plain.remove(&key.to_string()).map(|(_, v)| v)),
+            Some(hash) => hash?,
+        };
+
+        let Some((found, value)) = self.find(executor, &hash, key)? else {
+            return Ok(None);
+        };
+
+        self.hashed.remove_if_mut(&hash, |_, bucket| {
+            bucket.retain(|(k, _)| !same(k, &found));
+            bucket.is_empty()
+        });
+
+        Ok(Some(value))
+    }
+
+    // The bucket is copied first, `__eq__` may use the collection itself
+    fn find(
+        &self,
+        executor: &Executor,
+        hash: &str,
+        key: &LoxObject,
+    ) -> LoxResult<Option<(LoxObject, V)>> {
+        let bucket = match self.hashed.get(hash) {
+            Some(bucket) => bucket.value().clone(),
+            None => return Ok(None),
+        };
+
+        for (candidate, value) in bucket {
+            if executor.keys_equal(key, &candidate)? {
+                return Ok(Some((candidate, value)));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+// Buckets only hold instances, which are the same when their ids are
+fn same(key: &LoxObject, other: &LoxObject) -> bool {
+    matches!((key, other), (LoxObject::Instance(id, ..), LoxObject::Instance(other_id, ..)) if id == other_id)
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use super::environment::Environment;
+use super::LoxObject;
+use crate::syntax::Statement;
+use crate::{LoxResult, TokenType};
+
+// A file that ran to the end, with the environment holding its declarations
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub environment: Arc<Environment>,
+    pub names: Vec<String>,
+    pub namespace: Option<LoxObject>,
+}
+
+pub enum Loading {
+    Fresh,
+    Running,
+    Loaded(Module),
+}
+
+// Remembers imported files by canonical path, so each one runs once even when imports form a
+// cycle or the same file is imported both plainly and with `as`
+#[derive(Debug, Default)]
+pub struct ModuleLoader {
+    // None while the file is still running
+    modules: Mutex<HashMap<PathBuf, Option<Module>>>,
+}
+
+impl ModuleLoader {
+    // Gives the canonical path back, and marks the file as running when it wasn't seen before
+    pub fn start(&self, path: &Path) -> LoxResult<(PathBuf, Loading)> {
+        let path = path.canonicalize()?;
+        let mut modules = self.modules.lock().unwrap();
+
+        let loading = match modules.get(&path) {
+            Some(Some(module)) => Loading::Loaded(module.clone()),
+            Some(None) => Loading::Running,
+            None => {
+                modules.insert(path.clone(), None);
+                Loading::Fresh
+            }
+        };
+
+        Ok((path, loading))
+    }
+
+    // A failed file is forgotten, so it can be imported again
+    pub fn finish(&self, path: PathBuf, module: Option<Module>) {
+        let mut modules = self.modules.lock().unwrap();
+
+        match module {
+            Some(module) => modules.insert(path, Some(module)),
+            None => modules.remove(&path),
+        };
+    }
+
+    // Keeps the first namespace built for a file, so every `as` gets the same one
+    pub fn keep_namespace(&self, path: &Path, namespace: LoxObject) -> LoxObject {
+        let mut modules = self.modules.lock().unwrap();
+
+        match modules.get_mut(path) {
+            Some(Some(module)) => module.namespace.get_or_insert(namespace).clone(),
+            _ => namespace,
+        }
+    }
+
+    pub fn clear(&self) {
+        self.modules.lock().unwrap().clear();
+    }
+}
+
+// Names of the top-level declarations, which become the fields of a namespace
+pub fn declared_names(statements: &[Arc<Statement>]) -> Vec<String> {
+    statements
+        .iter()
+        .filter_map(|statement| match statement.as_ref() {
+            Statement::Var(name, _)
+            | Statement::AwaitVar(name, _)
+            | Statement::Function(name, ..)
+            | Statement::Class(name, ..) => match &name.kind {
+                TokenType::Identifier(name) => Some(name.to_owned()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+// Imports are relative to the importing file, or to the working directory without one
+pub fn resolve_path(importer: Option<&Path>, path: &str) -> PathBuf {
+    match importer.and_then(Path::parent) {
+        Some(directory) => directory.join(path),
+        None => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_path() {
+        assert_eq!(
+            resolve_path(Some(Path::new("scripts/main.lox")), "lib/math.lox"),
+            PathBuf::from("scripts/lib/math.lox")
+        );
+        assert_eq!(
+            resolve_path(Some(Path::new("scripts/main.lox")), "/abs/math.lox"),
+            PathBuf::from("/abs/math.lox")
+        );
+        assert_eq!(resolve_path(None, "math.lox"), PathBuf::from("math.lox"));
+    }
+
+    #[test]
+    fn test_start_once() {
+        let loader = ModuleLoader::default();
+        let path = std::env::temp_dir();
+
+        assert!(matches!(loader.start(&path).unwrap().1, Loading::Fresh));
+        assert!(matches!(loader.start(&path).unwrap().1, Loading::Running));
+        assert!(loader.start(Path::new("does/not/exist.lox")).is_err());
+    }
+}
@@ -1,13 +1,20 @@
+use crate::standard;
+use crate::syntax::expression::{LoxLiteral, Operator};
 use crate::{LoxError, LoxResult, Token, TokenType, NUMBER_PREC};
 
+use ahash::AHashMap;
 use dashmap::DashMap;
-use rug::Float;
-use std::sync::Arc;
+use rug::float::Round;
+use rug::{Float, Integer};
+use std::fs::File;
+use std::sync::{Arc, Mutex, RwLock};
 
+use std::fmt::Display;
 use std::ops;
 
 use super::class::LoxClass;
-use super::LoxCallable;
+use super::keyed::Keyed;
+use super::{environment, Environment, FunctionName, LoxCallable};
 
 #[derive(Debug)]
 pub enum LoxObject {
@@ -21,6 +28,11 @@ pub enum LoxObject {
     LoxString(Arc<String>),
     Boolean(bool),
     Callable(Arc<LoxCallable>),
+    Set(Arc<Keyed<()>>),
+    List(Arc<RwLock<Vec<LoxObject>>>),
+    Map(Arc<Keyed<LoxObject>>),
+    File(Arc<Mutex<Option<File>>>),
+    Future(Arc<Environment>),
 }
 
 impl LoxObject {
@@ -34,6 +46,70 @@ impl LoxObject {
         }
     }
 
+    pub fn as_i64(&self) -> LoxResult<i64> {
+        let n = self.as_integer()?;
+        n.to_i64().ok_or_else(|| Self::out_of_range(&n))
+    }
+
+    pub fn as_usize(&self) -> LoxResult<usize> {
+        let n = self.as_integer()?;
+        n.to_usize().ok_or_else(|| Self::out_of_range(&n))
+    }
+
+    // Numbers are always floats, so natives that need integers go through here
+    pub fn as_integer(&self) -> LoxResult<Integer> {
+        match self {
+            Self::Number(n) if n.is_integer() => Ok(n.to_integer().unwrap()),
+            _ => Err(LoxError::TypeError {
+                excepted_type: "integer".into(),
+            }),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Nil => "nil",
+            Self::Instance(..) => "instance",
+            Self::Number(..) => "number",
+            Self::LoxString(..) => "string",
+            Self::Boolean(..) => "bool",
+            Self::Callable(callable) => match callable.as_ref() {
+                LoxCallable::Class { .. } => "class",
+                _ => "function",
+            },
+            Self::Set(..) => "set",
+            Self::List(..) => "list",
+            Self::Map(..) => "map",
+            Self::File(..) => "file",
+            Self::Future(..) => "future",
+        }
+    }
+
+    // `>>` keeps the sign, `>>>` treats the number as a 64-bit two's complement value
+    pub fn shift(&self, operator: &Operator, amount: &LoxObject) -> LoxResult<LoxObject> {
+        let n = self.as_integer()?;
+        let amount = u32::try_from(amount.as_usize()?).map_err(|_| LoxError::RuntimeError {
+            line: None,
+            msg: "Shift amount is too large".into(),
+        })?;
+
+        let result = match operator {
+            Operator::ShiftLeft => n << amount,
+            Operator::ShiftRight => n >> amount,
+            Operator::UnsignedShiftRight => n.keep_bits(SHIFT_WIDTH) >> amount,
+            _ => unreachable!(),
+        };
+
+        Ok(LoxObject::from(Float::with_val(NUMBER_PREC, result)))
+    }
+
+    fn out_of_range(n: &Integer) -> LoxError {
+        LoxError::RuntimeError {
+            line: None,
+            msg: format!("{n} is out of range"),
+        }
+    }
+
     pub fn is_equal(&self, rhs: &LoxObject) -> LoxObject {
         use LoxObject::{Boolean, Nil};
 
@@ -62,9 +138,7 @@ impl LoxObject {
         if let (Number(l), Number(r)) = (self, rhs) {
             Ok(Self::from(l > r))
         } else {
-            Err(LoxError::TypeError {
-                excepted_type: "Number".into(),
-            })
+            Err(operand_error("compare", self, rhs))
         }
     }
 
@@ -80,9 +154,7 @@ impl LoxObject {
         if let (Number(l), Number(r)) = (self, rhs) {
             Ok(Self::from(l < r))
         } else {
-            Err(LoxError::TypeError {
-                excepted_type: "Number".into(),
-            })
+            Err(operand_error("compare", self, rhs))
         }
     }
 
@@ -92,7 +164,78 @@ impl LoxObject {
         ))
     }
 
+    pub fn calculate(
+        self,
+        operator: &Operator,
+        rhs: LoxObject,
+        round: Round,
+    ) -> LoxResult<LoxObject> {
+        use LoxObject::Number;
+
+        if let (Number(l), Number(r)) = (&self, &rhs) {
+            let (l, r) = (l.as_ref(), r.as_ref());
+            let prec = result_prec(l, r);
+
+            let (value, _) = match operator {
+                Operator::Star => Float::with_val_round(prec, l * r, round),
+                Operator::Slash => Float::with_val_round(prec, l / r, round),
+                Operator::Minus => Float::with_val_round(prec, l - r, round),
+                Operator::Plus => Float::with_val_round(prec, l + r, round),
+                // Truncated, so the result has the sign of the dividend
+                Operator::Remainder => Float::with_val_round(prec, l % r, round),
+                _ => unreachable!(),
+            };
+
+            return Ok(LoxObject::from(value));
+        }
+
+        match operator {
+            Operator::Star => self * rhs,
+            Operator::Slash => self / rhs,
+            Operator::Minus => self - rhs,
+            Operator::Plus => self + rhs,
+            Operator::Remainder => Err(operand_error("take the remainder of", &self, &rhs)),
+            _ => unreachable!(),
+        }
+    }
+
     pub fn get(&self, method: &Token) -> LoxResult<LoxObject> {
+        if let (LoxObject::Number(..) | LoxObject::LoxString(..), TokenType::Identifier(name)) =
+            (self, &method.kind)
+        {
+            let native = if let LoxObject::Number(..) = self {
+                standard::number::find_method(name)
+            } else {
+                standard::string::find_method(name)
+            };
+
+            return match native {
+                Some((arity, fun)) => Ok(LoxObject::from(LoxCallable::NativeMethod {
+                    arity,
+                    fun,
+                    receiver: LoxObject::from(self),
+                })),
+                None => Err(LoxError::RuntimeError {
+                    line: Some(method.line),
+                    msg: format!("Undefined property {name}."),
+                }),
+            };
+        }
+
+        if let (LoxObject::Callable(callable), TokenType::Identifier(name)) = (self, &method.kind) {
+            if let LoxCallable::Class { class } = callable.as_ref() {
+                return match class.find_static_owner(name) {
+                    Some(owner) => Ok(LoxObject::from(
+                        owner.static_fields.get(name).unwrap().value(),
+                    )),
+                    None => Err(LoxError::RuntimeError {
+                        line: Some(method.line),
+                        msg: format!("Undefined static field {name}."),
+                    }),
+                };
+            }
+        }
+
         if let (LoxObject::Instance(.., class, fields), TokenType::Identifier(name)) =
             (self, &method.kind)
         {
@@ -117,6 +260,18 @@ impl LoxObject {
         {
             fields.insert(name.to_owned(), value.clone());
 
+            Ok(value)
+        } else if let (LoxObject::Callable(callable), TokenType::Identifier(name)) =
+            (self, &method.kind)
+        {
+            if let LoxCallable::Class { class } = callable.as_ref() {
+                class
+                    .find_static_owner(name)
+                    .unwrap_or(class)
+                    .static_fields
+                    .insert(name.to_owned(), value.clone());
+            }
+
             Ok(value)
         } else {
             // Executor already checks that
@@ -127,8 +282,34 @@ impl LoxObject {
 
 impl PartialEq for LoxObject {
     fn eq(&self, other: &Self) -> bool {
+        self.structural_eq(other, &mut vec![])
+    }
+}
+
+impl LoxObject {
+    // Collections are compared by their contents. Pairs that are already being compared
+    // are assumed equal, so self-referential collections don't recurse forever.
+    fn structural_eq(&self, other: &Self, visited: &mut Vec<(usize, usize)>) -> bool {
         use LoxObject::*;
 
+        macro_rules! guard {
+            ($l:expr, $r:expr, $compare:expr) => {{
+                let pair = (
+                    Arc::as_ptr($l) as *const () as usize,
+                    Arc::as_ptr($r) as *const () as usize,
+                );
+
+                if Arc::ptr_eq($l, $r) || visited.contains(&pair) {
+                    true
+                } else {
+                    visited.push(pair);
+                    let result = $compare;
+                    visited.pop();
+                    result
+                }
+            }};
+        }
+
         match (self, other) {
             (Nil, Nil) => true,
             (Instance(id, ..), Instance(other_id, ..)) => id == other_id,
@@ -136,23 +317,216 @@ impl PartialEq for LoxObject {
             (LoxString(str), LoxString(other_str)) => str == other_str,
             (Boolean(bool), Boolean(other_bool)) => bool == other_bool,
             (Callable(callable), Callable(other_callable)) => callable == other_callable,
+            (Set(set), Set(other_set)) => {
+                guard!(set, other_set, set.same_as(other_set, |_, _| true))
+            }
+            (List(list), List(other_list)) => guard!(list, other_list, {
+                let (list, other_list) = (list.read().unwrap(), other_list.read().unwrap());
+
+                list.len() == other_list.len()
+                    && list
+                        .iter()
+                        .zip(other_list.iter())
+                        .all(|(l, r)| l.structural_eq(r, visited))
+            }),
+            (Map(map), Map(other_map)) => guard!(map, other_map, {
+                map.same_as(other_map, |value, other| {
+                    value.structural_eq(other, visited)
+                })
+            }),
+            (File(file), File(other_file)) => Arc::ptr_eq(file, other_file),
+            (Future(future), Future(other_future)) => Arc::ptr_eq(future, other_future),
             _ => false,
         }
     }
 }
 
+impl LoxObject {
+    // Numbers reaching the threshold, even inside collections, are written in scientific
+    // notation. Without one, numbers keep rug's own notation.
+    pub fn to_string_with(&self, sci_threshold: Option<u32>) -> String {
+        match self {
+            LoxObject::Number(n) => format_number(n, sci_threshold),
+            LoxObject::List(..) | LoxObject::Map(..) => {
+                self.collection_to_string(&mut vec![], sci_threshold)
+            }
+            other => other.to_string(),
+        }
+    }
+
+    // Collections that are already being printed show up as `[...]` or `{...}`
+    fn collection_to_string(&self, visited: &mut Vec<usize>, sci_threshold: Option<u32>) -> String {
+        use LoxObject::*;
+
+        let (id, placeholder) = match self {
+            List(list) => (Arc::as_ptr(list) as *const () as usize, "[...]"),
+            Map(map) => (Arc::as_ptr(map) as *const () as usize, "{...}"),
+            other => return other.to_string_with(sci_threshold),
+        };
+
+        if visited.contains(&id) {
+            return placeholder.into();
+        }
+
+        visited.push(id);
+
+        let result = match self {
+            List(list) => {
+                let values = list.read().unwrap();
+                let values = values
+                    .iter()
+                    .map(|i| i.collection_to_string(visited, sci_threshold))
+                    .collect::<Vec<_>>();
+
+                format!("[{}]", values.join(", "))
+            }
+            Map(map) => {
+                let mut entries = map
+                    .entries()
+                    .into_iter()
+                    .map(|(key, value)| {
+                        format!(
+                            "{key}: {}",
+                            value.collection_to_string(visited, sci_threshold)
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                entries.sort();
+
+                format!("{{{}}}", entries.join(", "))
+            }
+            _ => unreachable!(),
+        };
+
+        visited.pop();
+        result
+    }
+}
+
+impl LoxObject {
+    // The copy to store in `environment`, functions, classes and instances that would keep it
+    // alive only point to it weakly. Copying it out with `from` makes them strong again.
+    pub fn weakened(self, environment: &Environment) -> Self {
+        use LoxObject::*;
+
+        match &self {
+            Callable(callable) => match callable.weakened(environment) {
+                Some(weak) => Callable(Arc::new(weak)),
+                None => self,
+            },
+            Instance(id, class, fields) => match class.weakened(environment) {
+                Some(weak) => Instance(*id, Arc::new(weak), Arc::clone(fields)),
+                None => self,
+            },
+            _ => self,
+        }
+    }
+
+    // Instances and collections get new containers, a deep copy copies their contents too.
+    // Copies are remembered by address, so shared parts and cycles stay shared in the copy.
+    pub fn copy(
+        &self,
+        deep: bool,
+        next_id: &dyn Fn() -> u64,
+        copies: &mut AHashMap<usize, LoxObject>,
+    ) -> LoxObject {
+        use LoxObject::*;
+
+        let address = match self {
+            List(list) => Arc::as_ptr(list) as *const () as usize,
+            Map(map) => Arc::as_ptr(map) as *const () as usize,
+            Instance(.., fields) => Arc::as_ptr(fields) as *const () as usize,
+            Set(set) => {
+                let new_set = Keyed::default();
+                set.plain.iter().for_each(|item| {
+                    new_set.plain.insert(item.key().to_owned(), ());
+                });
+                set.hashed.iter().for_each(|bucket| {
+                    new_set
+                        .hashed
+                        .insert(bucket.key().to_owned(), bucket.value().clone());
+                });
+                return Set(Arc::new(new_set));
+            }
+            other => return other.clone(),
+        };
+
+        if let Some(copy) = copies.get(&address) {
+            return copy.clone();
+        }
+
+        let copy_item = |item: &LoxObject, copies: &mut AHashMap<usize, LoxObject>| {
+            if deep {
+                item.copy(true, next_id, copies)
+            } else {
+                item.clone()
+            }
+        };
+
+        match self {
+            List(list) => {
+                let new_list = Arc::new(RwLock::new(vec![]));
+                copies.insert(address, List(Arc::clone(&new_list)));
+
+                let items = list
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .map(|item| copy_item(item, copies))
+                    .collect();
+                *new_list.write().unwrap() = items;
+
+                List(new_list)
+            }
+            Map(map) => {
+                let new_map = Arc::new(Keyed::default());
+                copies.insert(address, Map(Arc::clone(&new_map)));
+
+                for entry in map.plain.iter() {
+                    new_map
+                        .plain
+                        .insert(entry.key().to_owned(), copy_item(entry.value(), copies));
+                }
+
+                // Keys stay the same instances, so they keep their hashes
+                for bucket in map.hashed.iter() {
+                    let entries = bucket
+                        .iter()
+                        .map(|(key, value)| (key.clone(), copy_item(value, copies)))
+                        .collect();
+                    new_map.hashed.insert(bucket.key().to_owned(), entries);
+                }
+
+                Map(new_map)
+            }
+            Instance(_, class, fields) => {
+                let new_fields = Arc::new(DashMap::with_hasher(ahash::RandomState::new()));
+                let copy = Instance(next_id(), Arc::clone(class), Arc::clone(&new_fields));
+                copies.insert(address, copy.clone());
+
+                for entry in fields.iter() {
+                    new_fields.insert(entry.key().to_owned(), copy_item(entry.value(), copies));
+                }
+
+                copy
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
 impl ops::Mul<LoxObject> for LoxObject {
     type Output = LoxResult<LoxObject>;
 
     fn mul(self, rhs: LoxObject) -> Self::Output {
         use LoxObject::Number;
 
-        if let (Number(l), Number(r)) = (self, rhs) {
-            Ok(LoxObject::from(Float::with_val(NUMBER_PREC, &*l * &*r)))
-        } else {
-            Err(LoxError::TypeError {
-                excepted_type: "Number".into(),
-            })
+        match (self, rhs) {
+            (Number(l), Number(r)) => Ok(LoxObject::from(Float::with_val(
+                result_prec(&l, &r),
+                &*l * &*r,
+            ))),
+            (l, r) => Err(operand_error("multiply", &l, &r)),
         }
     }
 }
@@ -163,12 +537,12 @@ impl ops::Div<LoxObject> for LoxObject {
     fn div(self, rhs: LoxObject) -> Self::Output {
         use LoxObject::Number;
 
-        if let (Number(l), Number(r)) = (self, rhs) {
-            Ok(LoxObject::from(Float::with_val(NUMBER_PREC, &*l / &*r)))
-        } else {
-            Err(LoxError::TypeError {
-                excepted_type: "Number".into(),
-            })
+        match (self, rhs) {
+            (Number(l), Number(r)) => Ok(LoxObject::from(Float::with_val(
+                result_prec(&l, &r),
+                &*l / &*r,
+            ))),
+            (l, r) => Err(operand_error("divide", &l, &r)),
         }
     }
 }
@@ -179,12 +553,12 @@ impl ops::Sub<LoxObject> for LoxObject {
     fn sub(self, rhs: LoxObject) -> Self::Output {
         use LoxObject::Number;
 
-        if let (Number(l), Number(r)) = (self, rhs) {
-            Ok(LoxObject::from(Float::with_val(NUMBER_PREC, &*l - &*r)))
-        } else {
-            Err(LoxError::TypeError {
-                excepted_type: "Number".into(),
-            })
+        match (self, rhs) {
+            (Number(l), Number(r)) => Ok(LoxObject::from(Float::with_val(
+                result_prec(&l, &r),
+                &*l - &*r,
+            ))),
+            (l, r) => Err(operand_error("subtract", &l, &r)),
         }
     }
 }
@@ -196,17 +570,100 @@ impl ops::Add<LoxObject> for LoxObject {
         use LoxObject::{LoxString, Number};
 
         if let (LoxString(l), r) = (&self, &rhs) {
-            Ok(LoxObject::from(format!("{}{}", l, r.to_string()).as_str()))
-        } else if let (Number(l), Number(r)) = (self, rhs) {
-            Ok(LoxObject::from(Float::with_val(NUMBER_PREC, &*l + &*r)))
+            Ok(LoxObject::from(format!("{}{}", l, r).as_str()))
         } else {
-            Err(LoxError::TypeError {
-                excepted_type: "Number".into(),
-            })
+            match (self, rhs) {
+                (Number(l), Number(r)) => Ok(LoxObject::from(Float::with_val(
+                    result_prec(&l, &r),
+                    &*l + &*r,
+                ))),
+                (l, r) => Err(operand_error("add", &l, &r)),
+            }
         }
     }
 }
 
+// Numbers whose decimal exponent reaches the threshold are written in scientific notation
+fn format_number(n: &Float, threshold: Option<u32>) -> String {
+    if n.is_zero() {
+        return "0".into();
+    }
+
+    let Some(threshold) = threshold.filter(|_| n.is_normal()) else {
+        // Only the mantissa loses its trailing zeros, `1e20` stays as it is
+        let result = n.to_string();
+        let (mantissa, exponent) = match result.split_once('e') {
+            Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+            None => (result.as_str(), None),
+        };
+        let mantissa = if mantissa.contains('.') {
+            mantissa.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            mantissa
+        };
+
+        return match exponent {
+            Some(exponent) => format!("{mantissa}e{exponent}"),
+            None => mantissa.into(),
+        };
+    };
+
+    // The value is 0.digits * 10^exponent
+    let (negative, digits, exponent) = n.to_sign_string_exp(10, None);
+    let digits = digits.trim_end_matches('0');
+    let exponent = exponent.unwrap();
+    let sign = if negative { "-" } else { "" };
+
+    if (exponent - 1).unsigned_abs() >= threshold {
+        let (first, rest) = digits.split_at(1);
+        let rest = if rest.is_empty() {
+            String::new()
+        } else {
+            format!(".{rest}")
+        };
+
+        format!("{sign}{first}{rest}e{}", exponent - 1)
+    } else if exponent <= 0 {
+        format!(
+            "{sign}0.{}{digits}",
+            "0".repeat(exponent.unsigned_abs() as usize)
+        )
+    } else if exponent as usize >= digits.len() {
+        format!(
+            "{sign}{digits}{}",
+            "0".repeat(exponent as usize - digits.len())
+        )
+    } else {
+        let (whole, fraction) = digits.split_at(exponent as usize);
+        format!("{sign}{whole}.{fraction}")
+    }
+}
+
+fn operand_error(operation: &'static str, left: &LoxObject, right: &LoxObject) -> LoxError {
+    LoxError::OperandTypeError {
+        operation,
+        left: left.type_name(),
+        right: right.type_name(),
+    }
+}
+
+// Width of the numbers `>>>` works on
+const SHIFT_WIDTH: u32 = 64;
+
+// Numbers from natives may carry a different precision, so the result keeps the higher one
+fn result_prec(l: &Float, r: &Float) -> u32 {
+    NUMBER_PREC.max(l.prec()).max(r.prec())
+}
+
+// The only place booleans get their Lox spelling, for values and literals alike
+pub fn bool_to_str(b: bool) -> &'static str {
+    if b {
+        "true"
+    } else {
+        "false"
+    }
+}
+
 impl From<&LoxObject> for bool {
     fn from(obj: &LoxObject) -> bool {
         use LoxObject::*;
@@ -239,6 +696,23 @@ impl From<&Float> for LoxObject {
     }
 }
 
+impl From<&LoxLiteral> for LoxObject {
+    fn from(literal: &LoxLiteral) -> LoxObject {
+        match literal {
+            LoxLiteral::Number(n) => Self::from(n),
+            LoxLiteral::LoxString(s) => Self::from(s.as_str()),
+            LoxLiteral::Bool(b) => Self::from(*b),
+            LoxLiteral::Nil => Self::Nil,
+        }
+    }
+}
+
+impl From<Vec<LoxObject>> for LoxObject {
+    fn from(values: Vec<LoxObject>) -> LoxObject {
+        Self::List(Arc::new(RwLock::new(values)))
+    }
+}
+
 impl From<LoxCallable> for LoxObject {
     fn from(value: LoxCallable) -> Self {
         Self::Callable(Arc::new(value))
@@ -251,41 +725,50 @@ impl From<&LoxCallable> for LoxObject {
     }
 }
 
-impl ToString for LoxObject {
-    fn to_string(&self) -> String {
+impl Display for LoxObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use LoxObject::*;
 
         match self {
-            Nil => String::from("nil"),
-            LoxString(s) => String::clone(s),
-            Number(n) => {
-                if **n == Float::with_val(NUMBER_PREC, 0) {
-                    String::from('0')
+            Nil => write!(f, "nil"),
+            LoxString(s) => write!(f, "{s}"),
+            Number(n) => write!(f, "{}", format_number(n, None)),
+            Boolean(b) => write!(f, "{}", bool_to_str(*b)),
+            Callable(callable) => match callable.as_ref() {
+                LoxCallable::Class { class } => write!(f, "#<class {}>", class),
+                LoxCallable::Function { name, .. } => match name {
+                    FunctionName::Named(name) => {
+                        write!(f, "<fun {name} arity: {}>", callable.arity())
+                    }
+                    FunctionName::Lambda => write!(f, "<lambda arity: {}>", callable.arity()),
+                    FunctionName::Method(class, name) => write!(f, "<method {class}.{name}>"),
+                },
+                _ if callable.is_variadic() => {
+                    write!(f, "<fun arity: {} or more>", callable.arity())
+                }
+                _ => write!(f, "<fun arity: {}>", callable.arity()),
+            },
+            Instance(id, class, ..) => write!(f, "#<{} instance as {}>", class.name, id),
+            Set(set) => write!(f, "#<set with {} values>", set.len()),
+            List(..) | Map(..) => write!(f, "{}", self.collection_to_string(&mut vec![], None)),
+            File(file) => {
+                if file.lock().unwrap().is_some() {
+                    write!(f, "#<file>")
                 } else {
-                    let result = n.to_string();
-                    let result = result.trim_end_matches('0').trim_end_matches('.');
-                    result.to_string()
+                    write!(f, "#<closed file>")
                 }
             }
-            Boolean(b) => b.to_string(),
-            Callable(callable) => {
-                if let LoxCallable::Class { class } = callable.as_ref() {
-                    format!("#<class {}>", class)
+            Future(future) => {
+                if environment::is_future_ready(future) {
+                    write!(f, "#<ready future>")
                 } else {
-                    format!("<fun arity: {}>", callable.arity())
+                    write!(f, "#<pending future>")
                 }
             }
-            Instance(id, class, ..) => format!("#<{} instance as {}>", class.name, id),
         }
     }
 }
 
-impl ToString for &LoxObject {
-    fn to_string(&self) -> String {
-        LoxObject::from(*self).to_string()
-    }
-}
-
 impl From<&LoxObject> for LoxObject {
     fn from(value: &LoxObject) -> Self {
         use LoxObject::*;
@@ -295,8 +778,22 @@ impl From<&LoxObject> for LoxObject {
             Number(num) => Number(Arc::clone(num)),
             Boolean(bool) => Boolean(*bool),
             Nil => Nil,
-            Callable(callable) => Callable(Arc::clone(callable)),
-            Instance(id, class, fields, ..) => Instance(*id, Arc::clone(class), Arc::clone(fields)),
+            Callable(callable) => match callable.strengthened() {
+                Some(strong) => Callable(Arc::new(strong)),
+                None => Callable(Arc::clone(callable)),
+            },
+            Instance(id, class, fields, ..) => Instance(
+                *id,
+                class
+                    .strengthened()
+                    .map_or_else(|| Arc::clone(class), Arc::new),
+                Arc::clone(fields),
+            ),
+            Set(set) => Set(Arc::clone(set)),
+            List(list) => List(Arc::clone(list)),
+            Map(map) => Map(Arc::clone(map)),
+            File(file) => File(Arc::clone(file)),
+            Future(future) => Future(Arc::clone(future)),
         }
     }
 }
@@ -306,3 +803,185 @@ impl Clone for LoxObject {
         LoxObject::from(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(n: f64) -> LoxObject {
+        LoxObject::from(Float::with_val(NUMBER_PREC, n))
+    }
+
+    #[test]
+    fn test_mixed_precision_arithmetic() {
+        let precise = || {
+            LoxObject::from(
+                Float::with_val(NUMBER_PREC * 2, 1) + Float::with_val(NUMBER_PREC * 2, -300).exp2(),
+            )
+        };
+        let coarse = || LoxObject::from(Float::with_val(24, 1));
+
+        for result in [
+            (precise() - coarse()).unwrap(),
+            (coarse() + precise()).unwrap(),
+            precise()
+                .calculate(&Operator::Star, coarse(), Round::Nearest)
+                .unwrap(),
+        ] {
+            if let LoxObject::Number(n) = result {
+                assert_eq!(n.prec(), NUMBER_PREC * 2);
+            } else {
+                panic!("{result} is not a number")
+            }
+        }
+
+        // The tiny part would be lost at the default precision
+        assert_ne!((precise() - coarse()).unwrap(), number(0.));
+        assert_eq!((coarse() * coarse()).unwrap(), number(1.));
+        assert_eq!(
+            precise().is_greater(&coarse()).unwrap(),
+            LoxObject::from(true)
+        );
+        assert_eq!(coarse().is_equal(&number(1.)), LoxObject::from(true));
+    }
+
+    #[test]
+    fn test_operand_type_errors() {
+        let message = |result: LoxResult<LoxObject>| result.unwrap_err().to_string();
+
+        assert!(message(number(1.) + LoxObject::Nil).contains("add number and nil"));
+        assert!(message(LoxObject::Nil - number(1.)).contains("subtract nil and number"));
+        assert!(message(number(1.) * LoxObject::from(true)).contains("multiply number and bool"));
+        assert!(message(LoxObject::from(true) / number(1.)).contains("divide bool and number"));
+        assert!(message(LoxObject::Nil.calculate(
+            &Operator::Remainder,
+            number(2.),
+            Round::Nearest
+        ))
+        .contains("remainder of nil and number"));
+        assert!(message(number(1.).is_less(&LoxObject::from("2"))).contains("number and string"));
+        assert!(message(LoxObject::Nil.is_greater_equal(&number(1.))).contains("nil and number"));
+    }
+
+    #[test]
+    fn test_sci_threshold() {
+        let big = Float::with_val(NUMBER_PREC, Float::u_pow_u(10, 20));
+
+        assert_eq!(format_number(&big, Some(21)), "100000000000000000000");
+        assert_eq!(format_number(&big, Some(20)), "1e20");
+        assert_eq!(format_number(&-big.clone(), Some(5)), "-1e20");
+        assert_eq!(
+            format_number(&Float::with_val(NUMBER_PREC, 0.25), Some(5)),
+            "0.25"
+        );
+        assert_eq!(
+            format_number(&Float::with_val(NUMBER_PREC, 1234.5), Some(5)),
+            "1234.5"
+        );
+        assert_eq!(
+            format_number(&Float::with_val(NUMBER_PREC, 1234.5), Some(3)),
+            "1.2345e3"
+        );
+        let small = Float::with_val(NUMBER_PREC, 1) / 1024;
+        assert_eq!(format_number(&small, Some(5)), "0.0009765625");
+        assert_eq!(format_number(&small, Some(3)), "9.765625e-4");
+        assert_eq!(format_number(&Float::new(NUMBER_PREC), Some(1)), "0");
+
+        // Without a threshold the exponent keeps its zeros
+        assert_eq!(
+            format_number(&Float::with_val(NUMBER_PREC, Float::u_pow_u(10, 100)), None),
+            "1e100"
+        );
+        assert!(format_number(
+            &Float::with_val(NUMBER_PREC, Float::i_pow_u(10, 20)).recip(),
+            None
+        )
+        .ends_with("e-20"));
+    }
+
+    #[test]
+    fn test_integral_numbers() {
+        assert_eq!(number(3.0).as_usize().unwrap(), 3);
+        assert_eq!(number(-3.0).as_i64().unwrap(), -3);
+    }
+
+    #[test]
+    fn test_non_integral_numbers() {
+        assert!(number(3.5).as_usize().is_err());
+        assert!(number(3.5).as_i64().is_err());
+        assert!(LoxObject::from("3").as_i64().is_err());
+        assert!(LoxObject::Nil.as_usize().is_err());
+    }
+
+    #[test]
+    fn test_out_of_range_numbers() {
+        assert!(number(-1.0).as_usize().is_err());
+        assert!(number(1e20).as_i64().is_err());
+        assert!(number(1e30).as_usize().is_err());
+        assert_eq!(number(-1e18).as_i64().unwrap(), -1_000_000_000_000_000_000);
+    }
+
+    fn list(values: Vec<LoxObject>) -> LoxObject {
+        LoxObject::from(values)
+    }
+
+    fn map(entries: Vec<(&str, LoxObject)>) -> LoxObject {
+        let map = Keyed::default();
+        for (key, value) in entries {
+            map.plain.insert(key.to_owned(), value);
+        }
+
+        LoxObject::Map(Arc::new(map))
+    }
+
+    #[test]
+    fn test_structural_equality() {
+        let nested = || list(vec![number(1.0), list(vec![LoxObject::from("a")])]);
+        assert_eq!(nested(), nested());
+        assert_ne!(
+            nested(),
+            list(vec![number(1.0), list(vec![LoxObject::from("b")])])
+        );
+        assert_ne!(nested(), list(vec![number(1.0)]));
+
+        let config = || map(vec![("a", number(1.0)), ("b", nested())]);
+        assert_eq!(config(), config());
+        assert_ne!(
+            config(),
+            map(vec![("a", number(1.0)), ("b", LoxObject::Nil)])
+        );
+        assert_ne!(config(), map(vec![("a", number(1.0))]));
+    }
+
+    #[test]
+    fn test_self_referential_equality() {
+        let (a, b) = (list(vec![]), list(vec![]));
+
+        for l in [&a, &b] {
+            if let LoxObject::List(inner) = l {
+                inner.write().unwrap().push(l.clone());
+            }
+        }
+
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_print_self_referential_collections() {
+        let a = list(vec![number(1.0)]);
+        if let LoxObject::List(inner) = &a {
+            inner.write().unwrap().push(a.clone());
+        }
+        assert_eq!(a.to_string(), "[1, [...]]");
+
+        let m = map(vec![("list", a.clone())]);
+        if let LoxObject::Map(inner) = &m {
+            inner.plain.insert("self".into(), m.clone());
+        }
+        assert_eq!(m.to_string(), "{list: [1, [...]], self: {...}}");
+
+        // The same list twice side by side isn't a cycle
+        let shared = list(vec![]);
+        assert_eq!(list(vec![shared.clone(), shared]).to_string(), "[[], []]");
+    }
+}
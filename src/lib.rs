@@ -0,0 +1,110 @@
+mod errors;
+mod executor;
+mod resolver;
+mod scanner;
+mod standard;
+mod syntax;
+
+pub use crate::errors::LoxError;
+pub use crate::errors::LoxResult;
+pub use crate::executor::{CancelToken, Executor, LoxObject, NativeFn, Output};
+pub use crate::resolver::Resolver;
+pub use crate::scanner::{default_keywords, Keywords, Scanner, Span, Token, TokenType};
+pub use crate::syntax::{Expression, Parser, Statement};
+use executor::Environment;
+use std::sync::Arc;
+use std::{num::NonZeroUsize, thread::available_parallelism};
+
+use lazy_static::lazy_static;
+use threadpool::ThreadPool;
+
+// pub const NUMBER_PREC: u32 = rug::float::prec_max();
+pub const NUMBER_PREC: u32 = 256;
+
+lazy_static! {
+    pub static ref WORKERS: ThreadPool = ThreadPool::new(
+        available_parallelism()
+            .unwrap_or(NonZeroUsize::new(1).unwrap())
+            .into()
+    );
+    static ref GLOBALS: Arc<Environment> = standard::globals();
+}
+
+/// Runs a whole program in a fresh interpreter.
+pub fn interpret(source: &str) -> LoxResult<()> {
+    let executor = Executor::new(&WORKERS);
+    let mut resolver = Resolver::new(&executor);
+
+    run(source, &mut resolver)
+}
+
+/// Runs a whole program in a fresh interpreter, writing `print` output to `output`.
+pub fn interpret_with_output(source: &str, output: Output) -> LoxResult<()> {
+    let executor = Executor::with_output(&WORKERS, output);
+    let mut resolver = Resolver::new(&executor);
+
+    run(source, &mut resolver)
+}
+
+/// Evaluates a single expression in a fresh interpreter and returns its value.
+pub fn eval(source: &str) -> LoxResult<LoxObject> {
+    let executor = Executor::new(&WORKERS);
+    let mut resolver = Resolver::new(&executor);
+
+    eval_with(source, &mut resolver)
+}
+
+/// Evaluates a single expression with an existing resolver, seeing its definitions.
+pub fn eval_with(source: &str, resolver: &mut Resolver) -> LoxResult<LoxObject> {
+    let expr = {
+        let tokens = Scanner::new(source).scan_tokens()?;
+        Parser::new(&tokens).parse_expression()?
+    };
+
+    let statements = Arc::new(vec![Arc::new(Statement::StmtExpression(expr))]);
+    resolver.resolve(Arc::clone(&statements))?;
+
+    Ok(resolver
+        .executor
+        .execute_returning(statements)?
+        .unwrap_or(LoxObject::Nil))
+}
+
+/// Runs a program with an existing resolver, so definitions survive between calls.
+pub fn run(code: &str, resolver: &mut Resolver) -> LoxResult<()> {
+    execute(code, resolver, false)
+}
+
+/// Like `run`, but a final expression without a semicolon is accepted and printed.
+pub fn run_repl(code: &str, resolver: &mut Resolver) -> LoxResult<()> {
+    execute(code, resolver, true)
+}
+
+/// Like `run`, but returns the value of the last statement when it is an expression.
+pub fn run_returning(code: &str, resolver: &mut Resolver) -> LoxResult<Option<LoxObject>> {
+    let stmt = parse(code, false)?;
+
+    resolver.resolve(Arc::clone(&stmt))?;
+
+    resolver.executor.execute_returning(stmt)
+}
+
+fn execute(code: &str, resolver: &mut Resolver, repl: bool) -> LoxResult<()> {
+    let stmt = parse(code, repl)?;
+
+    resolver.resolve(Arc::clone(&stmt))?;
+
+    resolver.executor.execute(Arc::clone(&stmt))?;
+
+    Ok(())
+}
+
+fn parse(code: &str, repl: bool) -> LoxResult<Arc<Vec<Arc<Statement>>>> {
+    let tokens = Scanner::new(code).scan_tokens()?;
+
+    if repl {
+        Parser::new_repl(&tokens).parse()
+    } else {
+        Parser::new(&tokens).parse()
+    }
+}
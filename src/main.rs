@@ -1,102 +1,290 @@
-mod errors;
-mod executor;
-mod resolver;
-mod scanner;
-mod standard;
-mod syntax;
-
-pub use crate::errors::LoxError;
-pub use crate::errors::LoxResult;
-pub use crate::scanner::{Token, TokenType};
-use executor::Environment;
-use executor::Executor;
-use resolver::Resolver;
-use scanner::Scanner;
 use std::env;
+use std::ffi::OsString;
 use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
 use std::process;
-use std::sync::Arc;
-use std::{num::NonZeroUsize, thread::available_parallelism};
-use syntax::Parser;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use lazy_static::lazy_static;
-use threadpool::ThreadPool;
+use rustyline::error::ReadlineError;
+use tarlox::{
+    eval_with, run, run_repl, run_returning, Executor, Expression, LoxError, LoxResult, Parser,
+    Resolver, Scanner, WORKERS,
+};
 
-// pub const NUMBER_PREC: u32 = rug::float::prec_max();
-pub const NUMBER_PREC: u32 = 256;
-
-lazy_static! {
-    static ref WORKERS: ThreadPool = ThreadPool::new(
-        available_parallelism()
-            .unwrap_or(NonZeroUsize::new(1).unwrap())
-            .into()
+fn main() {
+    let (flags, mut args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+    let strict = flags.iter().any(|flag| flag == "--strict");
+    let trace = flags.iter().any(|flag| flag == "--trace");
+    let color = color_enabled(
+        flags.iter().any(|flag| flag == "--no-color"),
+        env::var_os("NO_COLOR"),
+        io::stdout().is_terminal(),
     );
-    static ref GLOBALS: Arc<Environment> = standard::globals();
-}
+    let max_time = flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix("--max-time="))
+        .map(
+            |seconds| match seconds.parse::<f64>().map(Duration::try_from_secs_f64) {
+                Ok(Ok(max_time)) => max_time,
+                _ => {
+                    println!("Invalid --max-time: {seconds}");
+                    process::exit(64);
+                }
+            },
+        );
 
-fn main() {
-    let mut args = env::args();
+    let sci_threshold = flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix("--sci-threshold="))
+        .map(|threshold| match threshold.parse::<u32>() {
+            Ok(threshold) => threshold,
+            Err(_) => {
+                println!("Invalid --sci-threshold: {threshold}");
+                process::exit(64);
+            }
+        });
+
+    if let Some(code) = flags.iter().find_map(|flag| flag.strip_prefix("--eval=")) {
+        let exe = executor(trace, max_time, sci_threshold);
+        let mut resolver = Resolver::new(&exe).strict(strict);
+
+        match run_returning(code, &mut resolver) {
+            Ok(Some(value)) => println!("{}", exe.show(&value)),
+            Ok(None) => (),
+            Err(e) => {
+                println!("{}", format_error(&e, color));
+                process::exit(65)
+            }
+        }
+
+        process::exit(0);
+    }
 
     use std::cmp::Ordering::*;
     match args.len().cmp(&2) {
         Greater => {
-            println!("Usage: tlox [script]");
+            println!(
+                "Usage: tlox [--strict] [--trace] [--no-color] [--max-time=SECONDS] [--sci-threshold=DIGITS] [--eval=CODE | script]"
+            );
             process::exit(64);
         }
         Equal => {
-            let _ = &args.next();
-            let path = &args.next().unwrap();
-            if let Ok(source_code) = fs::read_to_string(path) {
-                let exe = Executor::new(&WORKERS);
-                let mut resolver = Resolver::new(&exe);
-
-                if let Err(e) = run(&source_code, &mut resolver) {
-                    println!("{e}");
+            let path = &args.remove(1);
+            match fs::read_to_string(path).map_err(LoxError::from) {
+                Ok(source_code) => {
+                    let exe = executor(trace, max_time, sci_threshold).with_script(Path::new(path));
+                    let mut resolver = Resolver::new(&exe).strict(strict);
+
+                    if let Err(e) = run(&source_code, &mut resolver) {
+                        println!("{}", format_error(&e, color));
+                        process::exit(65)
+                    }
+                }
+                Err(LoxError::FileError) => {
+                    println!("File not found: {path}");
+                    process::exit(65)
+                }
+                Err(e) => {
+                    println!("Can't read {path}: {e}");
                     process::exit(65)
                 }
-            } else {
-                println!("File not found: {path}");
-                process::exit(65)
             }
 
             process::exit(0);
         }
-        Less => run_prompt(),
+        Less => run_prompt(strict, trace, color, sci_threshold),
     }
 }
 
-fn run_prompt() {
-    let exe = Executor::new(&WORKERS);
-    let mut resolver = Resolver::new(&exe);
+// Errors are colored on terminals, unless NO_COLOR is set to anything but an empty value
+fn color_enabled(no_color: bool, no_color_env: Option<OsString>, is_terminal: bool) -> bool {
+    !no_color && no_color_env.is_none_or(|value| value.is_empty()) && is_terminal
+}
+
+fn format_error(error: &LoxError, color: bool) -> String {
+    if color {
+        format!("\x1b[31m{error}\x1b[0m")
+    } else {
+        error.to_string()
+    }
+}
+
+// The time budget only applies to scripts, not to REPL sessions
+fn executor(trace: bool, max_time: Option<Duration>, sci_threshold: Option<u32>) -> Executor {
+    let exe = Executor::new(&WORKERS).with_sci_threshold(sci_threshold);
+    if let Some(max_time) = max_time {
+        exe.set_max_time(max_time);
+    }
+
+    if trace {
+        exe.with_trace(Arc::new(Mutex::new(io::stderr())))
+    } else {
+        exe
+    }
+}
+
+fn run_prompt(strict: bool, trace: bool, color: bool, sci_threshold: Option<u32>) {
+    let exe = executor(trace, None, sci_threshold);
+    let mut resolver = Resolver::new(&exe).strict(strict).incremental(true);
 
     let mut rl = rustyline::DefaultEditor::new().unwrap();
     loop {
-        let readline = rl.readline("Tarbetu's Lox>> ");
-
-        match readline {
-            Ok(input) => {
-                if input.is_empty() {
-                    break;
+        match prompt_step(rl.readline("Tarbetu's Lox>> ")) {
+            PromptStep::Run(input) => {
+                let result = match input.strip_prefix(':') {
+                    Some(command) => meta_command(command, &mut resolver)
+                        .map(|output| output.into_iter().for_each(|line| println!("{line}"))),
+                    None => run_repl(&input, &mut resolver),
                 };
 
-                if let Err(e) = run(&input, &mut resolver) {
-                    println!("{e}\n");
+                if let Err(e) = result {
+                    println!("{}\n", format_error(&e, color));
                 };
             }
-            Err(_) => break,
+            PromptStep::Skip => continue,
+            PromptStep::Exit => break,
+            PromptStep::Fail(e) => {
+                println!("Can't read the input: {e}");
+                process::exit(74)
+            }
         }
     }
 }
 
-fn run(code: &str, resolver: &mut Resolver) -> LoxResult<()> {
-    let stmt = {
-        let tokens = Scanner::new(code).scan_tokens()?;
-        Parser::new(&tokens).parse()?
-    };
+// Lines starting with ':' inspect or change the session instead of running code
+fn meta_command(command: &str, resolver: &mut Resolver) -> LoxResult<Option<String>> {
+    let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
 
-    resolver.resolve(Arc::clone(&stmt))?;
+    match name {
+        "type" => {
+            let tokens = Scanner::new(argument).scan_tokens()?;
 
-    resolver.executor.execute(Arc::clone(&stmt))?;
+            if assigns(&Parser::new(&tokens).parse_expression()?) {
+                return Err(LoxError::RuntimeError {
+                    line: None,
+                    msg: "':type' can't check an expression that assigns".into(),
+                });
+            }
+            Ok(Some(eval_with(argument, resolver)?.type_name().into()))
+        }
+        "reset" => {
+            resolver.reset();
+            Ok(None)
+        }
+        _ => Err(LoxError::RuntimeError {
+            line: None,
+            msg: format!("Unknown command ':{name}', try ':type EXPR' or ':reset'"),
+        }),
+    }
+}
+
+// Whether evaluating the expression would change a variable or a field. Lambda bodies don't
+// run, so they are not checked.
+fn assigns(expr: &Expression) -> bool {
+    use Expression::*;
 
-    Ok(())
+    match expr {
+        Assign(..) | Increment(..) | Set(..) => true,
+        Binary(left, _, right) | Logical(left, _, right) => assigns(left) || assigns(right),
+        Comparison(first, rest) => {
+            assigns(first) || rest.iter().any(|(_, operand)| assigns(operand))
+        }
+        Unary(_, inner) | Grouping(inner) | Get(inner, _) => assigns(inner),
+        Call(callee, _, arguments) => {
+            assigns(callee) || arguments.iter().any(|argument| assigns(argument))
+        }
+        Match(_, value, arms) => assigns(value) || arms.iter().any(|(_, arm)| assigns(arm)),
+        Literal(_) | Variable(_) | Lambda(..) | This(_) | Super(..) => false,
+    }
+}
+
+#[derive(Debug)]
+enum PromptStep {
+    Run(String),
+    Skip,
+    Exit,
+    Fail(ReadlineError),
+}
+
+fn prompt_step(readline: Result<String, ReadlineError>) -> PromptStep {
+    match readline {
+        Ok(input) if input.is_empty() => PromptStep::Exit,
+        Ok(input) => PromptStep::Run(input),
+        // Ctrl-C only drops the current line, Ctrl-D leaves
+        Err(ReadlineError::Interrupted) => PromptStep::Skip,
+        Err(ReadlineError::Eof) => PromptStep::Exit,
+        Err(e) => PromptStep::Fail(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meta_commands() {
+        let exe = Executor::with_output(&WORKERS, Arc::new(Mutex::new(vec![])));
+        let mut resolver = Resolver::new(&exe);
+
+        assert_eq!(
+            meta_command("type 1 + 1", &mut resolver).unwrap(),
+            Some("number".into())
+        );
+
+        run("await_var x = \"text\";", &mut resolver).unwrap();
+        assert_eq!(
+            meta_command("type x", &mut resolver).unwrap(),
+            Some("string".into())
+        );
+
+        // Checking the type doesn't change state
+        run("var n = 1;", &mut resolver).unwrap();
+        for command in ["type n = 5", "type n++", "type (--n)", "type 1 + (n = 2)"] {
+            let error = meta_command(command, &mut resolver).unwrap_err();
+            assert!(error.to_string().contains("assigns"), "{command}: {error}");
+        }
+        assert_eq!(eval_with("n", &mut resolver).unwrap().to_string(), "1");
+
+        assert_eq!(meta_command("reset", &mut resolver).unwrap(), None);
+        assert!(meta_command("type x", &mut resolver).is_err());
+        assert!(meta_command("nope", &mut resolver).is_err());
+    }
+
+    #[test]
+    fn test_error_color() {
+        let error = LoxError::FileError;
+
+        assert!(format_error(&error, true).contains('\x1b'));
+        assert!(!format_error(&error, false).contains('\x1b'));
+
+        assert!(color_enabled(false, None, true));
+        assert!(color_enabled(false, Some("".into()), true));
+        assert!(!color_enabled(true, None, true));
+        assert!(!color_enabled(false, Some("1".into()), true));
+        assert!(!color_enabled(false, None, false));
+    }
+
+    #[test]
+    fn test_prompt_step() {
+        assert!(matches!(
+            prompt_step(Err(ReadlineError::Interrupted)),
+            PromptStep::Skip
+        ));
+        assert!(matches!(
+            prompt_step(Err(ReadlineError::Eof)),
+            PromptStep::Exit
+        ));
+        assert!(matches!(prompt_step(Ok(String::new())), PromptStep::Exit));
+        assert!(matches!(
+            prompt_step(Ok("print 1;".into())),
+            PromptStep::Run(input) if input == "print 1;"
+        ));
+        assert!(matches!(
+            prompt_step(Err(ReadlineError::Io(io::ErrorKind::BrokenPipe.into()))),
+            PromptStep::Fail(..)
+        ));
+    }
 }
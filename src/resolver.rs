@@ -2,7 +2,7 @@ use ahash::AHashMap;
 use std::sync::Arc;
 
 use crate::{
-    executor::Executor,
+    executor::{callable::ARGUMENTS, Executor, LoxObject},
     syntax::{Expression, Statement},
     LoxError::ParseError,
     LoxResult, Token, TokenType,
@@ -16,6 +16,12 @@ enum FunctionType {
     Initializer,
 }
 
+#[derive(Clone, Copy)]
+enum LoopType {
+    None,
+    Loop,
+}
+
 #[derive(Clone, Copy)]
 enum ClassType {
     None,
@@ -28,6 +34,12 @@ pub struct Resolver<'a> {
     scopes: Vec<AHashMap<String, bool>>,
     current_function: FunctionType,
     current_class: ClassType,
+    current_loop: LoopType,
+    strict: bool,
+    // For the REPL, every submission is resolved against the same global scope
+    incremental: bool,
+    // Functions, lambdas and classes resolved so far, they may keep their scope alive
+    closures: usize,
 }
 
 impl<'a> Resolver<'a> {
@@ -37,6 +49,10 @@ impl<'a> Resolver<'a> {
             scopes: vec![],
             current_function: FunctionType::None,
             current_class: ClassType::None,
+            current_loop: LoopType::None,
+            strict: false,
+            incremental: false,
+            closures: 0,
         };
 
         result.begin_scope();
@@ -44,7 +60,39 @@ impl<'a> Resolver<'a> {
         result
     }
 
+    // Strict mode rejects likely mistakes such as `if (x = 5)`
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    // Globals may be declared again, and they are looked up by name instead of by distance,
+    // so code resolved earlier keeps working when a later submission redefines them
+    pub fn incremental(mut self, incremental: bool) -> Self {
+        self.incremental = incremental;
+        self
+    }
+
+    // Starts a fresh session, forgetting the definitions here and in the executor
+    pub fn reset(&mut self) {
+        self.executor.reset();
+        self.scopes.clear();
+        self.begin_scope();
+    }
+
     pub fn resolve(&mut self, statements: Arc<Vec<Arc<Statement>>>) -> LoxResult<()> {
+        // A submission that failed halfway may have left its scopes behind,
+        // or a global it was still declaring
+        if self.incremental {
+            self.scopes.truncate(1);
+            self.scopes[0]
+                .values_mut()
+                .for_each(|defined| *defined = true);
+            self.current_function = FunctionType::None;
+            self.current_class = ClassType::None;
+            self.current_loop = LoopType::None;
+        }
+
         for statement in statements.iter() {
             self.resolve_statement(statement)?;
         }
@@ -63,40 +111,52 @@ impl<'a> Resolver<'a> {
             Block(..) => self.block_statement(statement),
             If(..) => self.if_statement(statement),
             While(..) => self.while_statement(statement),
+            Repeat(..) => self.repeat_statement(statement),
             Return(..) => self.return_statement(statement),
+            Continue(..) | Break(..) => self.jump_statement(statement),
+            Import(..) => self.import_statement(statement),
             Function(..) => self.function_statement(statement),
             Class(..) => self.class_statement(statement),
         }
     }
 
-    fn resolve_expression(&mut self, expression: &Expression) -> LoxResult<()> {
+    pub fn resolve_expression(&mut self, expression: &Expression) -> LoxResult<()> {
         use Expression::*;
 
         match expression {
             Binary(..) => self.binary_expression(expression),
+            Comparison(..) => self.comparison_expression(expression),
             Unary(..) => self.unary_expression(expression),
             Grouping(..) => self.grouping_expression(expression),
             Logical(..) => self.logical_expression(expression),
             Variable(..) => self.variable_expression(expression),
             Assign(..) => self.assignment_expression(expression),
+            Increment(assign, _) => self.resolve_expression(assign),
             Call(..) => self.call_expression(expression),
             Lambda(..) => self.lambda_expression(expression),
             Get(..) => self.get_expression(expression),
             Set(..) => self.set_expression(expression),
             This(..) => self.this_expression(expression),
             Super(..) => self.super_expression(expression),
+            Match(..) => self.match_expression(expression),
             Literal(..) => Ok(()),
         }
     }
 
     fn block_statement(&mut self, statement: &Statement) -> LoxResult<()> {
-        if let Statement::Block(body) = statement {
+        if let Statement::Block(body, poolable) = statement {
+            let closures = self.closures;
+
             self.begin_scope();
             for statement in body.as_ref() {
                 self.resolve_statement(statement.as_ref())?;
             }
             self.end_scope();
 
+            if self.closures == closures {
+                poolable.set();
+            }
+
             Ok(())
         } else {
             unreachable!()
@@ -139,6 +199,7 @@ impl<'a> Resolver<'a> {
                 if scope.get(&name.to_string()) == Some(&false) {
                     return Err(ParseError {
                         line: Some(name.line),
+                        span: None,
                         msg: "Can't read local variable its own initializer".into(),
                     });
                 }
@@ -175,8 +236,11 @@ impl<'a> Resolver<'a> {
 
     fn resolve_function(&mut self, function: &Statement, f_type: FunctionType) -> LoxResult<()> {
         if let Statement::Function(_, params, body) = function {
+            self.closures += 1;
             let enclosing_function = self.current_function;
+            let enclosing_loop = self.current_loop;
             self.current_function = f_type;
+            self.current_loop = LoopType::None;
 
             self.begin_scope();
 
@@ -184,11 +248,13 @@ impl<'a> Resolver<'a> {
                 self.declare(i)?;
                 self.define(i);
             }
+            self.define_arguments();
 
             self.resolve_statement(body)?;
             self.end_scope();
 
             self.current_function = enclosing_function;
+            self.current_loop = enclosing_loop;
             Ok(())
         } else {
             unreachable!()
@@ -197,6 +263,7 @@ impl<'a> Resolver<'a> {
 
     fn class_statement(&mut self, statement: &Statement) -> LoxResult<()> {
         if let Statement::Class(name, superclass, methods) = statement {
+            self.closures += 1;
             let enclosing_class = self.current_class;
             self.current_class = ClassType::Class;
             self.declare(name)?;
@@ -208,6 +275,7 @@ impl<'a> Resolver<'a> {
                 if name.to_string() == superclass_name.to_string() {
                     return Err(ParseError {
                         line: Some(superclass_name.line),
+                        span: None,
                         msg: "A class can't inherit from itself.".into(),
                     });
                 }
@@ -221,12 +289,23 @@ impl<'a> Resolver<'a> {
                     .and_then(|scope| scope.insert(format!("{:?}", TokenType::Super), true));
             }
 
+            // Static fields are evaluated where the class is declared
+            for field in methods.iter() {
+                if let Statement::Var(_, Some(initializer)) = field {
+                    self.resolve_expression(initializer)?;
+                }
+            }
+
             self.begin_scope();
             self.scopes
                 .last_mut()
                 .and_then(|scope| scope.insert(format!("{:?}", TokenType::This), true));
 
             for method in methods {
+                if let Statement::Var(..) = method {
+                    continue;
+                }
+
                 let mut declaration = FunctionType::Method;
 
                 if let Statement::Function(
@@ -273,6 +352,7 @@ impl<'a> Resolver<'a> {
 
     fn if_statement(&mut self, statement: &Statement) -> LoxResult<()> {
         if let Statement::If(condition, then_branch, else_branch) = statement {
+            self.check_condition(condition)?;
             self.resolve_expression(condition)?;
             self.resolve_statement(then_branch)?;
 
@@ -291,11 +371,13 @@ impl<'a> Resolver<'a> {
             if let FunctionType::None = self.current_function {
                 Err(ParseError {
                     line: None,
+                    span: None,
                     msg: "Can't return from top-level code.".into(),
                 })
             } else if let FunctionType::Initializer = self.current_function {
                 Err(ParseError {
                     line: None,
+                    span: None,
                     msg: "Can't return inside from initializer".into(),
                 })
             } else {
@@ -306,11 +388,42 @@ impl<'a> Resolver<'a> {
         }
     }
 
+    // Imported files are resolved on their own when they run, only the namespace is declared here
+    fn import_statement(&mut self, statement: &Statement) -> LoxResult<()> {
+        if let Statement::Import(_, _, namespace) = statement {
+            if let Some(name) = namespace {
+                self.declare(name)?;
+                self.define(name);
+            }
+
+            Ok(())
+        } else {
+            unreachable!()
+        }
+    }
+
+    fn jump_statement(&mut self, statement: &Statement) -> LoxResult<()> {
+        if let Statement::Continue(keyword) | Statement::Break(keyword) = statement {
+            if let LoopType::None = self.current_loop {
+                Err(ParseError {
+                    line: Some(keyword.line),
+                    span: None,
+                    msg: format!("Can't use '{}' outside of a loop.", keyword.kind),
+                })
+            } else {
+                Ok(())
+            }
+        } else {
+            unreachable!()
+        }
+    }
+
     fn this_expression(&mut self, expression: &Expression) -> LoxResult<()> {
         if let Expression::This(keyword) = expression {
             if let ClassType::None = self.current_class {
                 Err(ParseError {
                     line: None,
+                    span: None,
                     msg: "Can't use 'this' outside of a class.".into(),
                 })
             } else {
@@ -326,12 +439,14 @@ impl<'a> Resolver<'a> {
             if let ClassType::None = self.current_class {
                 Err(ParseError {
                     line: Some(keyword.line),
+                    span: None,
                     msg: "Can't use 'super' outside of a class.".into(),
                 })
             } else {
                 let ClassType::Subclass = self.current_class else {
                     return Err(ParseError {
                         line: Some(keyword.line),
+                        span: None,
                         msg: "Can't use 'super' with no superclass.".into(),
                     });
                 };
@@ -343,10 +458,46 @@ impl<'a> Resolver<'a> {
     }
 
     fn while_statement(&mut self, statement: &Statement) -> LoxResult<()> {
-        if let Statement::While(condition, body) = statement {
+        if let Statement::While(condition, body, increments) = statement {
+            let enclosing_loop = self.current_loop;
+            self.current_loop = LoopType::Loop;
+
+            self.check_condition(condition)?;
             self.resolve_expression(condition)?;
             self.resolve_statement(body)?;
 
+            for increment in increments {
+                self.resolve_expression(increment)?;
+            }
+
+            self.current_loop = enclosing_loop;
+            Ok(())
+        } else {
+            unreachable!()
+        }
+    }
+
+    // An assignment needs its own parentheses to be used as a condition in strict mode
+    fn check_condition(&self, condition: &Expression) -> LoxResult<()> {
+        match condition {
+            Expression::Assign(name, _) if self.strict => Err(ParseError {
+                line: Some(name.line),
+                span: None,
+                msg: "Assignment used as a condition, did you mean '=='?".into(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn repeat_statement(&mut self, statement: &Statement) -> LoxResult<()> {
+        if let Statement::Repeat(_, count, body) = statement {
+            let enclosing_loop = self.current_loop;
+            self.current_loop = LoopType::Loop;
+
+            self.resolve_expression(count)?;
+            self.resolve_statement(body)?;
+
+            self.current_loop = enclosing_loop;
             Ok(())
         } else {
             unreachable!()
@@ -364,20 +515,65 @@ impl<'a> Resolver<'a> {
         }
     }
 
+    fn comparison_expression(&mut self, expression: &Expression) -> LoxResult<()> {
+        if let Expression::Comparison(first, rest) = expression {
+            self.resolve_expression(first)?;
+            for (_, operand) in rest {
+                self.resolve_expression(operand)?;
+            }
+
+            Ok(())
+        } else {
+            unreachable!()
+        }
+    }
+
     fn call_expression(&mut self, expression: &Expression) -> LoxResult<()> {
-        if let Expression::Call(callee, _, arguments) = expression {
+        if let Expression::Call(callee, paren, arguments) = expression {
             self.resolve_expression(callee)?;
 
             for argument in arguments {
                 self.resolve_expression(argument)?;
             }
 
+            self.fold_call(callee, paren, arguments);
             Ok(())
         } else {
             unreachable!()
         }
     }
 
+    // A name declared in no scope is a global, and globals can't replace natives. So a call of
+    // such a name with literals may be worked out now, if the native is pure.
+    fn fold_call(&self, callee: &Expression, paren: &Token, arguments: &[Arc<Expression>]) {
+        let Expression::Variable(token) = callee else {
+            return;
+        };
+        let TokenType::Identifier(name) = &token.kind else {
+            return;
+        };
+
+        if self
+            .scopes
+            .iter()
+            .any(|scope| scope.contains_key(&token.to_string()))
+        {
+            return;
+        }
+
+        let literals = arguments
+            .iter()
+            .map(|argument| match argument.as_ref() {
+                Expression::Literal(literal) => Some(LoxObject::from(literal)),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>();
+
+        if let Some(literals) = literals {
+            self.executor.fold_call(paren, name, literals);
+        }
+    }
+
     fn get_expression(&mut self, expression: &Expression) -> LoxResult<()> {
         if let Expression::Get(object, ..) = expression {
             self.resolve_expression(object)
@@ -424,8 +620,11 @@ impl<'a> Resolver<'a> {
 
     fn lambda_expression(&mut self, expression: &Expression) -> LoxResult<()> {
         if let Expression::Lambda(params, body) = expression {
+            self.closures += 1;
             let enclosing_function = self.current_function;
+            let enclosing_loop = self.current_loop;
             self.current_function = FunctionType::Function;
+            self.current_loop = LoopType::None;
 
             self.begin_scope();
 
@@ -433,10 +632,26 @@ impl<'a> Resolver<'a> {
                 self.declare(i)?;
                 self.define(i);
             }
+            self.define_arguments();
             self.resolve_statement(body)?;
             self.end_scope();
 
             self.current_function = enclosing_function;
+            self.current_loop = enclosing_loop;
+
+            Ok(())
+        } else {
+            unreachable!()
+        }
+    }
+
+    fn match_expression(&mut self, expression: &Expression) -> LoxResult<()> {
+        if let Expression::Match(_, value, arms) = expression {
+            self.resolve_expression(value)?;
+
+            for (_, arm) in arms {
+                self.resolve_expression(arm)?;
+            }
 
             Ok(())
         } else {
@@ -451,6 +666,7 @@ impl<'a> Resolver<'a> {
             .enumerate()
             .rev()
             .find(|(_, scope)| scope.contains_key(name.to_string().as_str()))
+            .filter(|(index, _)| !(self.incremental && *index == 0))
         {
             self.executor
                 .resolve(name.id, expression, self.scopes.len() - 1 - index);
@@ -468,10 +684,13 @@ impl<'a> Resolver<'a> {
     }
 
     fn declare(&mut self, name: &Token) -> LoxResult<()> {
+        let redeclarable = self.incremental && self.scopes.len() == 1;
+
         if let Some(scope) = self.scopes.last_mut() {
-            if scope.contains_key(name.to_string().as_str()) {
+            if scope.contains_key(name.to_string().as_str()) && !redeclarable {
                 Err(ParseError {
                     line: Some(name.line),
+                    span: None,
                     msg: "Already a variable with this name in this scope.".into(),
                 })
             } else {
@@ -483,6 +702,11 @@ impl<'a> Resolver<'a> {
         }
     }
 
+    // Every function body can read its arguments as a list
+    fn define_arguments(&mut self) {
+        self.define(&Token::new(TokenType::Identifier(ARGUMENTS.into()), 0));
+    }
+
     fn define(&mut self, name: &Token) {
         if let Some(scope) = self.scopes.last_mut() {
             scope.insert(name.to_string(), true);
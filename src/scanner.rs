@@ -1,58 +1,122 @@
 mod token;
 mod token_type;
 
-pub use token::Token;
+pub use token::{Span, Token};
 pub use token_type::TokenType;
 
 use std::iter::Peekable;
 use std::str::Chars;
 
+use ahash::AHashMap;
+use lazy_static::lazy_static;
 use rug::Float;
 
 use crate::{LoxError, LoxResult, NUMBER_PREC};
 
+lazy_static! {
+    static ref DEFAULT_KEYWORDS: Keywords = default_keywords();
+}
+
+// Like Peekable<Chars>, but knows how far into the source it is
+#[derive(Clone)]
+struct Cursor<'a> {
+    chars: Peekable<Chars<'a>>,
+    offset: usize,
+}
+
+impl Cursor<'_> {
+    fn next(&mut self) -> Option<char> {
+        self.next_if(|_| true)
+    }
+
+    fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+
+    fn next_if(&mut self, func: impl FnOnce(&char) -> bool) -> Option<char> {
+        let next = self.chars.next_if(func)?;
+        self.offset += next.len_utf8();
+
+        Some(next)
+    }
+
+    fn next_if_eq(&mut self, expected: &char) -> Option<char> {
+        self.next_if(|next| next == expected)
+    }
+}
+
 // Our scanner is cool, but it can be improved
 // For example, we don't need any string allocations
 // Some methods are repeatible
 // We may prefer to include whole source to our struct instead of our "chars"
 // Anyway, Peakable is so cool.
 pub struct Scanner<'a> {
-    chars: Peekable<Chars<'a>>,
-    tokens: LoxResult<Vec<Token>>,
+    chars: Cursor<'a>,
+    tokens: Vec<Token>,
+    errors: Vec<LoxError>,
     line: usize,
+    // Where the token being scanned begins
+    start: usize,
+    keywords: &'a Keywords,
 }
 
 impl<'a> Scanner<'a> {
     pub fn new(source: &'a str) -> Self {
+        Self::with_keywords(source, &DEFAULT_KEYWORDS)
+    }
+
+    pub fn with_keywords(source: &'a str, keywords: &'a Keywords) -> Self {
         Self {
-            chars: source.chars().peekable(),
-            tokens: Ok(Vec::with_capacity(source.len())),
+            chars: Cursor {
+                chars: source.chars().peekable(),
+                offset: 0,
+            },
+            tokens: Vec::with_capacity(source.len()),
+            errors: vec![],
             line: 1,
+            start: 0,
+            keywords,
         }
     }
 
-    pub fn scan_tokens(mut self) -> LoxResult<Vec<Token>> {
-        while self.chars.peek().is_some() {
+    pub fn scan_tokens(self) -> LoxResult<Vec<Token>> {
+        match self.scan_until_error() {
+            (_, Some(error)) => Err(error),
+            (tokens, None) => Ok(tokens),
+        }
+    }
+
+    // Stops at the first error, keeping the tokens before it. EOF is only added on success.
+    fn scan_until_error(mut self) -> (Vec<Token>, Option<LoxError>) {
+        while self.chars.peek().is_some() && self.errors.is_empty() {
             self.scan_token();
+        }
 
-            if self.tokens.is_err() {
-                break;
-            }
+        if self.errors.is_empty() {
+            self.add_eof();
         }
 
-        if self.tokens.is_ok() {
-            self.tokens
-                .as_mut()
-                .unwrap()
-                .push(Token::new(TokenType::EOF, self.line));
+        let error = self.errors.into_iter().next();
+
+        (self.tokens, error)
+    }
+
+    // Keeps scanning after errors, so tooling can report all of them at once
+    pub fn scan_all(mut self) -> (Vec<Token>, Vec<LoxError>) {
+        while self.chars.peek().is_some() {
+            self.scan_token();
         }
 
-        self.tokens
+        self.add_eof();
+
+        (self.tokens, self.errors)
     }
 
     fn scan_token(&mut self) {
         use TokenType::*;
 
+        self.start = self.chars.offset;
+
         if let Some(next_char) = self.chars.next() {
             match next_char {
                 '(' => {
@@ -74,10 +138,18 @@ impl<'a> Scanner<'a> {
                     self.add_token(Dot);
                 }
                 '-' => {
-                    self.add_token(Minus);
+                    if self.chars.next_if_eq(&'-').is_some() {
+                        self.add_token(MinusMinus);
+                    } else {
+                        self.add_token(Minus);
+                    }
                 }
                 '+' => {
-                    self.add_token(Plus);
+                    if self.chars.next_if_eq(&'+').is_some() {
+                        self.add_token(PlusPlus);
+                    } else {
+                        self.add_token(Plus);
+                    }
                 }
                 ';' => {
                     self.add_token(Semicolon);
@@ -85,6 +157,9 @@ impl<'a> Scanner<'a> {
                 '*' => {
                     self.add_token(Star);
                 }
+                '%' => {
+                    self.add_token(Percent);
+                }
                 '!' => {
                     if self.chars.next_if_eq(&'=').is_some() {
                         self.add_token(BangEqual);
@@ -95,6 +170,8 @@ impl<'a> Scanner<'a> {
                 '=' => {
                     if self.chars.next_if_eq(&'=').is_some() {
                         self.add_token(EqualEqual);
+                    } else if self.chars.next_if_eq(&'>').is_some() {
+                        self.add_token(Arrow);
                     } else {
                         self.add_token(Equal);
                     }
@@ -102,6 +179,8 @@ impl<'a> Scanner<'a> {
                 '<' => {
                     if self.chars.next_if_eq(&'=').is_some() {
                         self.add_token(LessEqual);
+                    } else if self.chars.next_if_eq(&'<').is_some() {
+                        self.add_token(LessLess);
                     } else {
                         self.add_token(Less);
                     }
@@ -109,35 +188,62 @@ impl<'a> Scanner<'a> {
                 '>' => {
                     if self.chars.next_if_eq(&'=').is_some() {
                         self.add_token(GreaterEqual);
+                    } else if self.chars.next_if_eq(&'>').is_some() {
+                        if self.chars.next_if_eq(&'>').is_some() {
+                            self.add_token(GreaterGreaterGreater);
+                        } else {
+                            self.add_token(GreaterGreater);
+                        }
                     } else {
                         self.add_token(Greater);
                     }
                 }
                 '/' => {
                     if self.chars.next_if_eq(&'/').is_some() {
-                        while !(self.chars.next() == Some('\n') || self.chars.peek().is_none()) {}
+                        while self.chars.next_if(|c| *c != '\n' && *c != '\r').is_some() {}
                     } else {
                         self.add_token(Slash);
                     }
                 }
-                ' ' | '\r' | '\t' => (),
-                '\n' => self.line += 1,
-                '"' => self.string(),
-                num if num.is_ascii_digit() => self.number(num),
-                alpha if alpha.is_ascii_alphabetic() => self.identifier(alpha),
-                unexcepted_char => {
-                    self.tokens = Err(LoxError::UnexceptedCharacter {
-                        line: self.line,
-                        character: unexcepted_char,
-                    })
+                ' ' | '\t' => (),
+                '\n' | '\r' => self.count_line(next_char),
+                '"' if self.chars.next_if_eq(&'"').is_some() => {
+                    if self.chars.next_if_eq(&'"').is_some() {
+                        self.triple_quoted_string()
+                    } else {
+                        self.add_token(LoxString(String::new()))
+                    }
                 }
+                '"' => self.string(false),
+                'r' if self.chars.next_if_eq(&'"').is_some() => self.string(true),
+                num if num.is_ascii_digit() => self.number(num),
+                alpha if alpha.is_ascii_alphabetic() || alpha == '_' => self.identifier(alpha),
+                unexcepted_char => self.errors.push(LoxError::UnexceptedCharacter {
+                    line: self.line,
+                    character: unexcepted_char,
+                }),
             }
         }
     }
 
     fn add_token(&mut self, kind: TokenType) {
-        if let Ok(tokens) = &mut self.tokens {
-            tokens.push(Token::new(kind, self.line))
+        let span = Span {
+            start: self.start,
+            end: self.chars.offset,
+        };
+
+        self.tokens.push(Token::with_span(kind, self.line, span))
+    }
+
+    fn add_eof(&mut self) {
+        self.start = self.chars.offset;
+        self.add_token(TokenType::EOF)
+    }
+
+    // `\n`, `\r` and `\r\n` are one line break each, the last one is counted at its `\n`
+    fn count_line(&mut self, char: char) {
+        if char == '\n' || (char == '\r' && self.chars.peek() != Some(&'\n')) {
+            self.line += 1;
         }
     }
 
@@ -145,7 +251,9 @@ impl<'a> Scanner<'a> {
     // Also, we don't need any String allocation.
     // This is easy for now, but should be replaced with substrings.
 
-    fn string(&mut self) {
+    // Raw strings keep their backslashes, others understand \n, \t, \r, \" and \\
+    fn string(&mut self, raw: bool) {
+        let start = self.line;
         let mut string = String::new();
 
         loop {
@@ -154,9 +262,63 @@ impl<'a> Scanner<'a> {
             match next_char {
                 Some('"') => break,
                 Some('\n') => self.line += 1,
-                Some(char) => string.push(char),
+                Some('\\') if !raw => match self.chars.next_if(|c| "ntr\"\\".contains(*c)) {
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some('r') => string.push('\r'),
+                    Some(escaped) => string.push(escaped),
+                    // Unknown escapes are kept as they are
+                    None => string.push('\\'),
+                },
+                Some(char) => {
+                    self.count_line(char);
+                    string.push(char)
+                }
+                None => {
+                    self.errors.push(LoxError::UnterminatedString(start));
+
+                    return;
+                }
+            }
+        }
+
+        self.add_token(TokenType::LoxString(string))
+    }
+
+    // Kept verbatim, except for a newline right after the opening quotes
+    fn triple_quoted_string(&mut self) {
+        let start = self.line;
+        let mut string = String::new();
+
+        if self.chars.next_if_eq(&'\r').is_some() {
+            self.chars.next_if_eq(&'\n');
+            self.line += 1;
+        } else if self.chars.next_if_eq(&'\n').is_some() {
+            self.line += 1;
+        }
+
+        loop {
+            match self.chars.next() {
+                Some('"') => {
+                    let mut quotes = 1;
+
+                    while quotes < 3 && self.chars.next_if_eq(&'"').is_some() {
+                        quotes += 1;
+                    }
+
+                    if quotes == 3 {
+                        break;
+                    }
+
+                    // Fewer than three quotes are part of the string
+                    string.push_str(&"\"".repeat(quotes));
+                }
+                Some(char) => {
+                    self.count_line(char);
+                    string.push(char)
+                }
                 None => {
-                    self.tokens = Err(LoxError::UnterminatedString);
+                    self.errors.push(LoxError::UnterminatedString(start));
 
                     return;
                 }
@@ -168,20 +330,16 @@ impl<'a> Scanner<'a> {
 
     fn number(&mut self, first_digit: char) {
         let mut string = String::from(first_digit);
+        let mut has_fraction = false;
 
         loop {
-            match self.chars.peek() {
+            match self.chars.peek().copied() {
                 Some(x) if x.is_ascii_digit() => string.push(self.chars.next().unwrap()),
-                Some('.') => {
-                    if let Some('.') = self.chars.next() {
-                        if self.chars.peek().is_some_and(|c| c.is_ascii_digit()) {
-                            string.push('.');
-                            string.push(self.chars.next().unwrap())
-                        } else {
-                            self.add_token(TokenType::Dot);
-                            break;
-                        }
-                    }
+                // A dot is only part of the number when a digit follows it,
+                // so `2.0` is a float but `2.foo` is a property access on `2`
+                Some('.') if !has_fraction && self.is_digit_after_dot() => {
+                    has_fraction = true;
+                    string.push(self.chars.next().unwrap());
                 }
                 None | Some(_) => break,
             }
@@ -193,6 +351,13 @@ impl<'a> Scanner<'a> {
         )))
     }
 
+    fn is_digit_after_dot(&self) -> bool {
+        let mut lookahead = self.chars.clone();
+        lookahead.next();
+
+        lookahead.peek().is_some_and(|c| c.is_ascii_digit())
+    }
+
     fn identifier(&mut self, first_digit: char) {
         let mut string = String::from(first_digit);
 
@@ -205,43 +370,59 @@ impl<'a> Scanner<'a> {
             }
         }
 
-        let token_type = {
-            use TokenType::*;
-            match string.as_str() {
-                "and" => And,
-                "class" => Class,
-                "else" => Else,
-                "false" => False,
-                "for" => For,
-                "fun" => Fun,
-                "if" => If,
-                "nil" => Nil,
-                "or" => Or,
-                "print" => Print,
-                "return" => Return,
-                "super" => Super,
-                "this" => This,
-                "true" => True,
-                "var" => Var,
-                "while" => While,
-                "is_ready" => IsReady,
-                "await_var" => AwaitVar,
-                "lambda" => Lambda,
-                _ => Identifier(string),
-            }
+        let token_type = match self.keywords.get(&string) {
+            Some(keyword) => keyword.clone(),
+            None => TokenType::Identifier(string),
         };
 
         self.add_token(token_type)
     }
 }
 
+// Spellings of the keywords, a scanner can use its own table to localize them
+pub type Keywords = AHashMap<String, TokenType>;
+
+pub fn default_keywords() -> Keywords {
+    use TokenType::*;
+
+    [
+        ("and", And),
+        ("class", Class),
+        ("else", Else),
+        ("false", False),
+        ("for", For),
+        ("fun", Fun),
+        ("if", If),
+        ("nil", Nil),
+        ("or", Or),
+        ("print", Print),
+        ("return", Return),
+        ("continue", Continue),
+        ("break", Break),
+        ("import", Import),
+        ("super", Super),
+        ("this", This),
+        ("true", True),
+        ("var", Var),
+        ("while", While),
+        ("repeat", Repeat),
+        ("is_ready", IsReady),
+        ("await_var", AwaitVar),
+        ("lambda", Lambda),
+        ("match", Match),
+    ]
+    .into_iter()
+    .map(|(spelling, keyword)| (spelling.to_owned(), keyword))
+    .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use rug::Float;
     use TokenType::*;
 
-    fn get_tokens(source: &'static str) -> LoxResult<Vec<Token>> {
+    fn get_tokens(source: &str) -> LoxResult<Vec<Token>> {
         Scanner::new(source).scan_tokens()
     }
 
@@ -263,6 +444,44 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_spans() {
+        let tokens = get_tokens("var name = \"hé\";\n  name >>> 2").unwrap();
+        let spans: Vec<(usize, usize)> = tokens
+            .iter()
+            .map(|token| (token.span.start, token.span.end))
+            .collect();
+
+        assert_eq!(
+            spans,
+            vec![
+                (0, 3),
+                (4, 8),
+                (9, 10),
+                (11, 16),
+                (16, 17),
+                (20, 24),
+                (25, 28),
+                (29, 30),
+                (30, 30)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_endings() {
+        for newline in ["\n", "\r\n", "\r"] {
+            let source = ["a // note", "\"one", "two\" \"\"\"", "x\"\"\" b", "", "c"].join(newline);
+            let lines: Vec<usize> = get_tokens(&source)
+                .unwrap()
+                .iter()
+                .map(|token| token.line)
+                .collect();
+
+            assert_eq!(lines, vec![1, 3, 4, 4, 6, 6], "{newline:?}");
+        }
+    }
+
     #[test]
     fn test_sum() {
         test_scanner(
@@ -391,4 +610,171 @@ mod tests {
     fn test_for_keyword() {
         test_scanner("for", vec![For])
     }
+
+    #[test]
+    fn test_trailing_comment() {
+        test_scanner(
+            "print a; // note",
+            vec![Print, Identifier(String::from("a")), Semicolon],
+        );
+
+        let tokens = get_tokens("// first\nprint a;").unwrap();
+        assert_eq!(tokens[0].line, 2);
+    }
+
+    #[test]
+    fn test_dot_after_number() {
+        let number = |n: f64| Number(Float::with_val(NUMBER_PREC, n));
+        let foo = || Identifier(String::from("foo"));
+
+        test_scanner("2.5", vec![number(2.5)]);
+        test_scanner("2.foo", vec![number(2.), Dot, foo()]);
+        test_scanner("2 .foo", vec![number(2.), Dot, foo()]);
+        test_scanner(
+            "(2).foo",
+            vec![LeftParen, number(2.), RightParen, Dot, foo()],
+        );
+        test_scanner("1.5.foo", vec![number(1.5), Dot, foo()]);
+        test_scanner("1.5.3", vec![number(1.5), Dot, number(3.)]);
+        test_scanner("2.", vec![number(2.), Dot]);
+    }
+
+    #[test]
+    fn test_number_followed_by_dots() {
+        let number = |n: f64| Number(Float::with_val(NUMBER_PREC, n));
+
+        test_scanner("1.", vec![number(1.), Dot]);
+        test_scanner("1..", vec![number(1.), Dot, Dot]);
+        test_scanner("1..2", vec![number(1.), Dot, Dot, number(2.)]);
+        test_scanner("1.25.3", vec![number(1.25), Dot, number(3.)]);
+    }
+
+    #[test]
+    fn test_custom_keywords() {
+        let mut keywords = default_keywords();
+        keywords.remove("if");
+        keywords.insert("eger".into(), If);
+        keywords.insert("yazdir".into(), Print);
+
+        let tokens = Scanner::with_keywords("eger (x) yazdir x; if", &keywords)
+            .scan_tokens()
+            .unwrap();
+
+        assert_eq!(
+            convert_tokens_into_token_types(tokens),
+            vec![
+                If,
+                LeftParen,
+                Identifier("x".into()),
+                RightParen,
+                Print,
+                Identifier("x".into()),
+                Semicolon,
+                Identifier("if".into()),
+                EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_increment_operators() {
+        test_scanner(
+            "++ -- + - - -",
+            vec![PlusPlus, MinusMinus, Plus, Minus, Minus, Minus],
+        );
+    }
+
+    #[test]
+    fn test_shift_operators() {
+        test_scanner(
+            "<< >> >>> >= <=",
+            vec![
+                LessLess,
+                GreaterGreater,
+                GreaterGreaterGreater,
+                GreaterEqual,
+                LessEqual,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_leading_underscore() {
+        test_scanner(
+            "_private __eq__",
+            vec![Identifier("_private".into()), Identifier("__eq__".into())],
+        );
+    }
+
+    #[test]
+    fn test_scan_all_reports_every_error() {
+        let (tokens, errors) = Scanner::new("var a = 1 @ 2;\nprint a # b;").scan_all();
+
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                LoxError::UnexceptedCharacter {
+                    line: 1,
+                    character: '@'
+                },
+                LoxError::UnexceptedCharacter {
+                    line: 2,
+                    character: '#'
+                }
+            ]
+        ));
+        assert_eq!(tokens.len(), 11);
+        assert_eq!(tokens.last().unwrap().kind, EOF);
+        assert!(get_tokens("var a = 1 @ 2;").is_err());
+    }
+
+    #[test]
+    fn test_scan_until_error_keeps_earlier_tokens() {
+        let (tokens, error) = Scanner::new("1 + @").scan_until_error();
+        let kinds: Vec<TokenType> = tokens.into_iter().map(|token| token.kind).collect();
+
+        assert_eq!(kinds, vec![Number(Float::with_val(NUMBER_PREC, 1)), Plus]);
+        assert!(matches!(
+            error,
+            Some(LoxError::UnexceptedCharacter {
+                line: 1,
+                character: '@'
+            })
+        ));
+    }
+
+    #[test]
+    fn test_escapes_and_raw_strings() {
+        test_scanner(
+            r#""a\nb" r"a\nb" "\"\\\t" "\q" r"C:\path""#,
+            vec![
+                LoxString("a\nb".into()),
+                LoxString(r"a\nb".into()),
+                LoxString("\"\\\t".into()),
+                LoxString(r"\q".into()),
+                LoxString(r"C:\path".into()),
+            ],
+        );
+        test_scanner(
+            r#"r rx"#,
+            vec![Identifier("r".into()), Identifier("rx".into())],
+        );
+    }
+
+    #[test]
+    fn test_triple_quoted_strings() {
+        let tokens = get_tokens("\"\"\"\nfirst \"quoted\"\nsecond\"\"\" x").unwrap();
+
+        assert_eq!(tokens[0].kind, LoxString("first \"quoted\"\nsecond".into()));
+        assert_eq!(tokens[1].line, 3);
+        test_scanner(
+            r#""" "a""#,
+            vec![LoxString("".into()), LoxString("a".into())],
+        );
+
+        assert!(matches!(
+            get_tokens("print 1;\n\"\"\"never\nclosed\"\""),
+            Err(LoxError::UnterminatedString(2))
+        ));
+    }
 }
@@ -3,19 +3,32 @@ use std::hash::Hash;
 
 use rand::random;
 
+// Byte offsets of a token in the source, empty for tokens made up by the interpreter
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct Token {
     pub id: usize,
     pub kind: super::TokenType,
     pub line: usize,
+    pub span: Span,
 }
 
 impl Token {
     pub fn new(kind: super::TokenType, line: usize) -> Token {
+        Self::with_span(kind, line, Span::default())
+    }
+
+    pub fn with_span(kind: super::TokenType, line: usize, span: Span) -> Token {
         Token {
             id: random(),
             kind,
             line,
+            span,
         }
     }
 }
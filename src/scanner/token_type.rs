@@ -1,3 +1,4 @@
+use std::fmt::Display;
 use std::hash::Hash;
 
 use rug::Float;
@@ -15,15 +16,22 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Percent,
     // one or two character tokens,
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
+    Arrow,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    PlusPlus,
+    MinusMinus,
+    GreaterGreater,
+    GreaterGreaterGreater,
     // literals,
     Identifier(String),
     LoxString(String),
@@ -41,18 +49,89 @@ pub enum TokenType {
     Print,
     IsReady,
     Return,
+    Continue,
+    Break,
+    Import,
     Super,
     This,
     True,
     Var,
     AwaitVar,
     While,
+    Repeat,
     Lambda,
+    Match,
 
     #[allow(clippy::upper_case_acronyms)]
     EOF,
 }
 
+// How the token is written in the source, for error messages
+impl Display for TokenType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use TokenType::*;
+
+        let text = match self {
+            LeftParen => "(",
+            RightParen => ")",
+            LeftBrace => "{",
+            RightBrace => "}",
+            Comma => ",",
+            Dot => ".",
+            Minus => "-",
+            Plus => "+",
+            Semicolon => ";",
+            Slash => "/",
+            Star => "*",
+            Percent => "%",
+            Bang => "!",
+            BangEqual => "!=",
+            Equal => "=",
+            EqualEqual => "==",
+            Arrow => "=>",
+            Greater => ">",
+            GreaterEqual => ">=",
+            Less => "<",
+            LessEqual => "<=",
+            LessLess => "<<",
+            PlusPlus => "++",
+            MinusMinus => "--",
+            GreaterGreater => ">>",
+            GreaterGreaterGreater => ">>>",
+            Identifier(name) => name,
+            LoxString(string) => return write!(f, "\"{string}\""),
+            Number(number) => return write!(f, "{}", number.to_f64()),
+            And => "and",
+            Class => "class",
+            Else => "else",
+            False => "false",
+            Fun => "fun",
+            For => "for",
+            If => "if",
+            Nil => "nil",
+            Or => "or",
+            Print => "print",
+            IsReady => "is_ready",
+            Return => "return",
+            Continue => "continue",
+            Break => "break",
+            Import => "import",
+            Super => "super",
+            This => "this",
+            True => "true",
+            Var => "var",
+            AwaitVar => "await_var",
+            While => "while",
+            Repeat => "repeat",
+            Lambda => "lambda",
+            Match => "match",
+            EOF => "end of input",
+        };
+
+        write!(f, "{text}")
+    }
+}
+
 impl Hash for TokenType {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         use TokenType::*;
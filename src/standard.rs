@@ -1,30 +1,126 @@
+mod arity;
+pub mod assert;
+pub mod breakpoint;
 mod clock;
+pub mod copy;
+pub mod debug_env;
+pub mod eval;
+mod file;
+pub mod future;
+mod is_nil;
+mod json;
+mod len;
+pub mod list;
+mod map;
+mod math;
+pub mod number;
+pub mod round_mode;
+mod set;
+pub mod string;
+mod timer;
 
-use crate::executor::{environment, Environment, LoxCallable, LoxObject};
+use crate::executor::{environment, Environment, Executor, LoxCallable};
+use crate::WORKERS;
 use std::sync::Arc;
 
-use dashmap::DashMap;
-use either::Either;
-
+// `pure` natives only read their arguments, `variadic` ones take any number after `arity`
+// and `executor` ones are given the executor and the call site
 macro_rules! make_function {
-    ($env:expr, $locals:expr, $arity:expr, $name:ident) => {
-        environment::put_immediately(
-            Arc::clone(&$env),
-            Arc::clone(&$locals),
-            stringify!($name),
-            Either::Right(LoxObject::from(LoxCallable::NativeFunction {
-                arity: $arity,
-                fun: $name::$name,
-            })),
-        )
+    (@put $env:expr, $executor:expr, $name:ident, $native:expr) => {
+        environment::put_native(Arc::clone(&$env), &$executor, stringify!($name), $native)
+    };
+    (@native $env:expr, $executor:expr, $arity:expr, $module:ident::$name:ident, $pure:expr, $variadic:expr) => {
+        make_function!(@put $env, $executor, $name, LoxCallable::NativeFunction {
+            arity: $arity,
+            fun: $module::$name,
+            pure: $pure,
+            variadic: $variadic,
+        })
+    };
+    ($env:expr, $executor:expr, $arity:expr, $name:ident $(, $kind:ident)?) => {
+        make_function!($env, $executor, $arity, $name::$name $(, $kind)?)
+    };
+    ($env:expr, $executor:expr, $arity:expr, $module:ident::$name:ident, executor) => {
+        make_function!(@put $env, $executor, $name, LoxCallable::ExecutorFunction {
+            arity: $arity,
+            fun: $module::$name,
+        })
+    };
+    ($env:expr, $executor:expr, $arity:expr, $module:ident::$name:ident, pure) => {
+        make_function!(@native $env, $executor, $arity, $module::$name, true, false)
+    };
+    ($env:expr, $executor:expr, $arity:expr, $module:ident::$name:ident, variadic) => {
+        make_function!(@native $env, $executor, $arity, $module::$name, false, true)
+    };
+    ($env:expr, $executor:expr, $arity:expr, $module:ident::$name:ident) => {
+        make_function!(@native $env, $executor, $arity, $module::$name, false, false)
     };
 }
 
 pub fn globals() -> Arc<Environment> {
     let env = Arc::new(Environment::default());
-    let locals = Arc::new(DashMap::with_hasher(ahash::RandomState::new()));
+    let executor = Executor::new(&WORKERS);
 
-    make_function!(env, locals, 0, clock);
+    make_function!(env, executor, 1, arity, pure);
+    make_function!(env, executor, 1, assert, executor);
+    make_function!(env, executor, 1, assert::assert_throws, executor);
+    make_function!(env, executor, 3, assert::assert_close);
+    make_function!(env, executor, 0, clock);
+    make_function!(env, executor, 0, timer::timer_start);
+    make_function!(env, executor, 1, timer::timer_elapsed);
+    make_function!(env, executor, 1, is_nil, pure);
+    make_function!(env, executor, 0, set);
+    make_function!(env, executor, 2, set::set_add, executor);
+    make_function!(env, executor, 2, set::set_has, executor);
+    make_function!(env, executor, 2, set::set_remove, executor);
+    make_function!(env, executor, 0, list);
+    make_function!(env, executor, 2, list::list_push);
+    make_function!(env, executor, 2, list::list_get, pure);
+    make_function!(env, executor, 1, list::head, pure);
+    make_function!(env, executor, 1, list::tail, pure);
+    make_function!(env, executor, 3, list::slice, pure);
+    make_function!(env, executor, 1, list::sort);
+    make_function!(env, executor, 2, list::sort_by, executor);
+    make_function!(env, executor, 1, list::list_min, pure);
+    make_function!(env, executor, 1, list::list_max, pure);
+    make_function!(env, executor, 0, map);
+    make_function!(env, executor, 3, map::map_set, executor);
+    make_function!(env, executor, 2, map::map_get, executor);
+    make_function!(env, executor, 2, map::has_key, executor);
+    make_function!(env, executor, 1, map::keys, pure);
+    make_function!(env, executor, 1, map::values, pure);
+    make_function!(env, executor, 1, map::entries, pure);
+    make_function!(env, executor, 1, json::to_json, pure);
+    make_function!(env, executor, 1, json::from_json, pure);
+    make_function!(env, executor, 2, file::open);
+    make_function!(env, executor, 2, file::fwrite);
+    make_function!(env, executor, 1, file::fclose);
+    make_function!(env, executor, 3, math::clamp, pure);
+    make_function!(env, executor, 1, math::sign, pure);
+    make_function!(env, executor, 1, math::trunc, pure);
+    make_function!(env, executor, 1, math::floor, pure);
+    make_function!(env, executor, 1, math::ceil, pure);
+    make_function!(env, executor, 1, math::round, pure);
+    make_function!(env, executor, 1, math::abs, pure);
+    make_function!(env, executor, 2, math::floor_mod, pure);
+    make_function!(env, executor, 1, string::upper, pure);
+    make_function!(env, executor, 1, string::format, variadic);
+    make_function!(env, executor, 1, len, pure);
+    make_function!(env, executor, 2, string::split, pure);
+    make_function!(env, executor, 2, string::contains, pure);
+    make_function!(env, executor, 3, string::rjust, pure);
+    make_function!(env, executor, 3, string::ljust, pure);
+    make_function!(env, executor, 1, math::to_hex, pure);
+    make_function!(env, executor, 1, math::to_bin, pure);
+    make_function!(env, executor, 1, round_mode::set_round_mode, executor);
+    make_function!(env, executor, 1, future::spawn, executor);
+    make_function!(env, executor, 1, future::await_all);
+    make_function!(env, executor, 0, future::pending_tasks);
+    make_function!(env, executor, 0, debug_env, executor);
+    make_function!(env, executor, 0, breakpoint, executor);
+    make_function!(env, executor, 1, eval, executor);
+    make_function!(env, executor, 1, copy::clone, executor);
+    make_function!(env, executor, 1, copy::deep_clone, executor);
 
     env
 }
@@ -0,0 +1,21 @@
+use rug::Float;
+
+use crate::{executor::LoxObject, LoxError, LoxResult, NUMBER_PREC};
+
+// Classes take as many arguments as their `init`, variadic natives have no arity
+pub fn arity(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    if let LoxObject::Callable(callable) = &arguments[0] {
+        if callable.is_variadic() {
+            return Ok(LoxObject::Nil);
+        }
+
+        Ok(LoxObject::from(Float::with_val(
+            NUMBER_PREC,
+            callable.arity(),
+        )))
+    } else {
+        Err(LoxError::TypeError {
+            excepted_type: "Function or Class".into(),
+        })
+    }
+}
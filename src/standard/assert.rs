@@ -0,0 +1,106 @@
+use rug::Float;
+
+use super::math::get_number;
+use crate::executor::{CallSite, Executor, LoxObject};
+use crate::{LoxError, LoxResult, NUMBER_PREC};
+
+// Reports the failing expression when it is written in the call
+pub fn assert(_: &Executor, site: &CallSite, arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    if bool::from(&arguments[0]) {
+        return Ok(LoxObject::Nil);
+    }
+
+    Err(LoxError::RuntimeError {
+        line: site.line,
+        msg: match site.arguments.first() {
+            Some(condition) => format!("Assertion failed: {condition}"),
+            None => "Assertion failed".into(),
+        },
+    })
+}
+
+pub fn assert_throws(
+    executor: &Executor,
+    site: &CallSite,
+    arguments: Vec<LoxObject>,
+) -> LoxResult<LoxObject> {
+    let callable = match &arguments[0] {
+        LoxObject::Callable(callable) if callable.arity() == 0 => callable,
+        _ => {
+            return Err(LoxError::RuntimeError {
+                line: site.line,
+                msg: "assert_throws expects a function without parameters".into(),
+            })
+        }
+    };
+
+    match callable.call(executor, vec![]) {
+        // Cancelling stops the whole script, it isn't an error of the function
+        Err(LoxError::Cancelled) => Err(LoxError::Cancelled),
+        Err(_) => Ok(LoxObject::Nil),
+        Ok(value) => {
+            let function = match site.arguments.first() {
+                Some(function) => function.to_string(),
+                None => arguments[0].to_string(),
+            };
+
+            Err(LoxError::RuntimeError {
+                line: site.line,
+                msg: format!("Assertion failed: {function} returned {value} instead of failing"),
+            })
+        }
+    }
+}
+
+// Computed numbers rarely match exactly, so they are compared within a tolerance
+pub fn assert_close(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let a = get_number(&arguments[0])?;
+    let b = get_number(&arguments[1])?;
+    let tolerance = get_number(&arguments[2])?;
+
+    if tolerance.is_sign_negative() || tolerance.is_nan() {
+        return Err(LoxError::RuntimeError {
+            line: None,
+            msg: format!(
+                "assert_close tolerance must not be negative, got {}",
+                arguments[2]
+            ),
+        });
+    }
+
+    if Float::with_val(NUMBER_PREC, a - b).abs() <= *tolerance {
+        Ok(LoxObject::Nil)
+    } else {
+        Err(LoxError::RuntimeError {
+            line: None,
+            msg: format!(
+                "Assertion failed: {} and {} differ by more than {}",
+                arguments[0], arguments[1], arguments[2]
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(n: f64) -> LoxObject {
+        LoxObject::from(Float::with_val(NUMBER_PREC, n))
+    }
+
+    #[test]
+    fn test_assert_close() {
+        assert!(assert_close(vec![number(0.1), number(0.1001), number(0.001)]).is_ok());
+        assert!(assert_close(vec![number(1.), number(1.), number(0.)]).is_ok());
+
+        let error = assert_close(vec![number(1.), number(1.5), number(0.1)]).unwrap_err();
+        assert!(
+            error.to_string().contains("1 and 1.5 differ by more than"),
+            "{error}"
+        );
+
+        assert!(assert_close(vec![number(1.), number(1.), number(-1.)]).is_err());
+        assert!(assert_close(vec![LoxObject::Nil, number(1.), number(1.)]).is_err());
+    }
+}
@@ -0,0 +1,6 @@
+use crate::executor::{CallSite, Executor, LoxObject};
+use crate::LoxResult;
+
+pub fn breakpoint(executor: &Executor, site: &CallSite, _: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    executor.breakpoint(site.line)
+}
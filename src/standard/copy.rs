@@ -0,0 +1,17 @@
+use ahash::AHashMap;
+
+use crate::executor::{CallSite, Executor, LoxObject};
+use crate::LoxResult;
+
+// Copied instances need new ids, so these take the executor
+pub fn clone(executor: &Executor, _: &CallSite, arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    Ok(arguments[0].copy(false, &|| executor.next_id(), &mut AHashMap::new()))
+}
+
+pub fn deep_clone(
+    executor: &Executor,
+    _: &CallSite,
+    arguments: Vec<LoxObject>,
+) -> LoxResult<LoxObject> {
+    Ok(arguments[0].copy(true, &|| executor.next_id(), &mut AHashMap::new()))
+}
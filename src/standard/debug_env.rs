@@ -0,0 +1,8 @@
+use crate::executor::{CallSite, Executor, LoxObject};
+use crate::LoxResult;
+
+// Written to stderr, so the program's own output stays as it is
+pub fn debug_env(executor: &Executor, _: &CallSite, _: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    eprint!("{}", executor.describe_scopes());
+    Ok(LoxObject::Nil)
+}
@@ -0,0 +1,18 @@
+use crate::executor::{CallSite, Executor, LoxObject};
+use crate::{LoxError, LoxResult};
+
+// Runs in the caller's environment, so definitions stay
+pub fn eval(
+    executor: &Executor,
+    site: &CallSite,
+    arguments: Vec<LoxObject>,
+) -> LoxResult<LoxObject> {
+    let LoxObject::LoxString(source) = &arguments[0] else {
+        return Err(LoxError::RuntimeError {
+            line: site.line,
+            msg: "eval expects a string".into(),
+        });
+    };
+
+    executor.eval_source(site.line, source)
+}
@@ -0,0 +1,106 @@
+use crate::{executor::LoxObject, LoxError, LoxResult};
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+pub fn open(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let (path, mode) = match (&arguments[0], &arguments[1]) {
+        (LoxObject::LoxString(path), LoxObject::LoxString(mode)) => (path, mode),
+        _ => {
+            return Err(LoxError::TypeError {
+                excepted_type: "String".into(),
+            })
+        }
+    };
+
+    let mut options = OpenOptions::new();
+
+    match mode.as_str() {
+        "w" => options.write(true).create(true).truncate(true),
+        "a" => options.append(true).create(true),
+        _ => {
+            return Err(LoxError::RuntimeError {
+                line: None,
+                msg: format!("Unknown file mode '{mode}'"),
+            })
+        }
+    };
+
+    let file = options.open(path.as_str())?;
+
+    Ok(LoxObject::File(Arc::new(Mutex::new(Some(file)))))
+}
+
+// Writes the value followed by a newline, like print
+pub fn fwrite(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let handle = get_file(&arguments)?;
+    let mut handle = handle.lock().unwrap();
+
+    match handle.as_mut() {
+        Some(file) => {
+            writeln!(file, "{}", arguments[1]).map_err(|_| LoxError::FileError)?;
+            Ok(LoxObject::Nil)
+        }
+        None => Err(LoxError::FileError),
+    }
+}
+
+pub fn fclose(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let handle = get_file(&arguments)?;
+
+    // Dropping the file closes it, a second close is an error
+    match handle.lock().unwrap().take() {
+        Some(mut file) => {
+            file.flush().map_err(|_| LoxError::FileError)?;
+            Ok(LoxObject::Nil)
+        }
+        None => Err(LoxError::FileError),
+    }
+}
+
+fn get_file(arguments: &[LoxObject]) -> LoxResult<&Arc<Mutex<Option<File>>>> {
+    if let Some(LoxObject::File(file)) = arguments.first() {
+        Ok(file)
+    } else {
+        Err(LoxError::TypeError {
+            excepted_type: "File".into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_and_read_back() {
+        let path = std::env::temp_dir().join(format!("tarlox-file-{}.txt", std::process::id()));
+        let path_object = LoxObject::from(path.to_str().unwrap());
+
+        let handle = open(vec![path_object.clone(), LoxObject::from("w")]).unwrap();
+        fwrite(vec![handle.clone(), LoxObject::from("first")]).unwrap();
+        fwrite(vec![handle.clone(), LoxObject::from("second")]).unwrap();
+        fclose(vec![handle.clone()]).unwrap();
+
+        let handle = open(vec![path_object, LoxObject::from("a")]).unwrap();
+        fwrite(vec![handle.clone(), LoxObject::Nil]).unwrap();
+        fclose(vec![handle.clone()]).unwrap();
+
+        assert!(matches!(
+            fwrite(vec![handle.clone(), LoxObject::from("late")]),
+            Err(LoxError::FileError)
+        ));
+        assert!(matches!(fclose(vec![handle]), Err(LoxError::FileError)));
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(content, "first\nsecond\nnil\n");
+    }
+
+    #[test]
+    fn test_unknown_mode() {
+        assert!(open(vec![LoxObject::from("unused"), LoxObject::from("rw")]).is_err());
+    }
+}
@@ -0,0 +1,49 @@
+use rug::Float;
+use std::sync::Arc;
+
+use crate::executor::{environment, CallSite, Executor, LoxObject};
+use crate::{LoxError, LoxResult, NUMBER_PREC, WORKERS};
+
+pub fn spawn(
+    executor: &Executor,
+    site: &CallSite,
+    arguments: Vec<LoxObject>,
+) -> LoxResult<LoxObject> {
+    match &arguments[0] {
+        LoxObject::Callable(callable) if callable.arity() == 0 => Ok(LoxObject::Future(
+            environment::spawn(executor, Arc::clone(callable)),
+        )),
+        _ => Err(LoxError::RuntimeError {
+            line: site.line,
+            msg: "spawn expects a function without parameters".into(),
+        }),
+    }
+}
+
+// Values that aren't futures are returned as they are
+pub fn await_all(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    if let LoxObject::List(list) = &arguments[0] {
+        let futures = list.read().unwrap().clone();
+
+        futures
+            .iter()
+            .map(|future| match future {
+                LoxObject::Future(future) => environment::wait_future(future),
+                other => Ok(other.clone()),
+            })
+            .collect::<LoxResult<Vec<_>>>()
+            .map(LoxObject::from)
+    } else {
+        Err(LoxError::TypeError {
+            excepted_type: "List".into(),
+        })
+    }
+}
+
+// Counts both running and queued jobs, including `var` initializers
+pub fn pending_tasks(_: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    Ok(LoxObject::from(Float::with_val(
+        NUMBER_PREC,
+        WORKERS.active_count() + WORKERS.queued_count(),
+    )))
+}
@@ -0,0 +1,5 @@
+use crate::{executor::LoxObject, LoxResult};
+
+pub fn is_nil(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    Ok(LoxObject::from(matches!(arguments[0], LoxObject::Nil)))
+}
@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use rug::Float;
+use serde_json::{Map, Number, Value};
+
+use crate::executor::{keyed::Keyed, LoxObject};
+use crate::{LoxError, LoxResult, NUMBER_PREC};
+
+// Numbers go through an f64, so anything beyond its precision is rounded.
+// Functions, classes, instances and sets have no JSON form and are reported as errors.
+pub fn to_json(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let value = into_json_value(&arguments[0])?;
+
+    Ok(LoxObject::from(value.to_string().as_str()))
+}
+
+pub fn from_json(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    if let LoxObject::LoxString(source) = &arguments[0] {
+        let value = serde_json::from_str(source).map_err(|e| LoxError::RuntimeError {
+            line: None,
+            msg: format!("Invalid JSON: {e}"),
+        })?;
+
+        from_json_value(value)
+    } else {
+        Err(LoxError::TypeError {
+            excepted_type: "String".into(),
+        })
+    }
+}
+
+fn into_json_value(object: &LoxObject) -> LoxResult<Value> {
+    use LoxObject::*;
+
+    match object {
+        Nil => Ok(Value::Null),
+        Boolean(b) => Ok(Value::Bool(*b)),
+        LoxString(s) => Ok(Value::String(s.to_string())),
+        Number(n) => {
+            let number = n
+                .to_integer()
+                .filter(|_| n.is_integer())
+                .and_then(|i| i.to_i64())
+                .map(serde_json::Number::from)
+                .or_else(|| serde_json::Number::from_f64(n.to_f64()));
+
+            number.map(Value::Number).ok_or(LoxError::RuntimeError {
+                line: None,
+                msg: format!("{} can't be represented in JSON", object),
+            })
+        }
+        List(list) => Ok(Value::Array(
+            list.read()
+                .unwrap()
+                .iter()
+                .map(into_json_value)
+                .collect::<LoxResult<_>>()?,
+        )),
+        Map(map) => {
+            let mut result = serde_json::Map::new();
+
+            for (key, value) in map.entries() {
+                result.insert(key.to_string(), into_json_value(&value)?);
+            }
+
+            Ok(Value::Object(result))
+        }
+        Instance(..) | Callable(..) | Set(..) | File(..) | Future(..) => {
+            Err(LoxError::RuntimeError {
+                line: None,
+                msg: format!("{} can't be serialized to JSON", object),
+            })
+        }
+    }
+}
+
+fn from_json_value(value: Value) -> LoxResult<LoxObject> {
+    match value {
+        Value::Null => Ok(LoxObject::Nil),
+        Value::Bool(b) => Ok(LoxObject::from(b)),
+        Value::String(s) => Ok(LoxObject::from(s.as_str())),
+        Value::Number(n) => from_json_number(n),
+        Value::Array(values) => Ok(LoxObject::from(
+            values
+                .into_iter()
+                .map(from_json_value)
+                .collect::<LoxResult<Vec<_>>>()?,
+        )),
+        Value::Object(entries) => from_json_object(entries),
+    }
+}
+
+fn from_json_number(n: Number) -> LoxResult<LoxObject> {
+    Float::parse(n.to_string())
+        .map(|parsed| LoxObject::from(Float::with_val(NUMBER_PREC, parsed)))
+        .map_err(|e| LoxError::InternalError(format!("Error while parsing JSON number: {e}")))
+}
+
+fn from_json_object(entries: Map<String, Value>) -> LoxResult<LoxObject> {
+    let map = Keyed::default();
+
+    for (key, value) in entries {
+        map.plain.insert(key, from_json_value(value)?);
+    }
+
+    Ok(LoxObject::Map(Arc::new(map)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(source: &str) -> String {
+        let object = from_json(vec![LoxObject::from(source)]).unwrap();
+
+        to_json(vec![object]).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_scalars() {
+        assert_eq!(round_trip("null"), "null");
+        assert_eq!(round_trip("true"), "true");
+        assert_eq!(round_trip("42"), "42");
+        assert_eq!(round_trip("2.5"), "2.5");
+        assert_eq!(round_trip(r#""lox""#), r#""lox""#);
+    }
+
+    #[test]
+    fn test_nested_structures() {
+        let source = r#"{"list":[1,[2,3],{"inner":null}],"name":"tarlox"}"#;
+
+        assert_eq!(round_trip(source), source);
+    }
+
+    #[test]
+    fn test_from_json_builds_lists_and_maps() {
+        let object = from_json(vec![LoxObject::from(r#"{"a":[1,2]}"#)]).unwrap();
+
+        assert_eq!(object.to_string(), "{a: [1, 2]}");
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        assert!(from_json(vec![LoxObject::from("{")]).is_err());
+    }
+
+    #[test]
+    fn test_set_is_an_error() {
+        let set = crate::standard::set::set(vec![]).unwrap();
+
+        assert!(to_json(vec![set]).is_err());
+    }
+}
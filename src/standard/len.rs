@@ -0,0 +1,37 @@
+use rug::Float;
+
+use crate::{executor::LoxObject, LoxError, LoxResult, NUMBER_PREC};
+
+pub fn len(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let len = match &arguments[0] {
+        LoxObject::LoxString(string) => string.chars().count(),
+        LoxObject::List(list) => list.read().unwrap().len(),
+        _ => {
+            return Err(LoxError::TypeError {
+                excepted_type: "String or List".into(),
+            })
+        }
+    };
+
+    Ok(LoxObject::from(Float::with_val(NUMBER_PREC, len)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_len() {
+        assert_eq!(
+            len(vec![LoxObject::from("héllo")]).unwrap().to_string(),
+            "5"
+        );
+        assert_eq!(
+            len(vec![LoxObject::from(vec![LoxObject::Nil, LoxObject::Nil])])
+                .unwrap()
+                .to_string(),
+            "2"
+        );
+        assert!(len(vec![LoxObject::Nil]).is_err());
+    }
+}
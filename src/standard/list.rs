@@ -0,0 +1,276 @@
+use crate::executor::{CallSite, Executor, LoxObject};
+use crate::{LoxError, LoxResult};
+
+use rug::Float;
+use std::cmp::Ordering;
+
+use std::sync::{Arc, RwLock};
+
+pub fn list(_: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    Ok(LoxObject::from(vec![]))
+}
+
+pub fn list_push(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let list = get_list(&arguments)?;
+    list.write().unwrap().push(arguments[1].clone());
+
+    Ok(LoxObject::Nil)
+}
+
+pub fn list_get(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let list = get_list(&arguments)?;
+    let index = arguments[1].as_usize()?;
+
+    list.read()
+        .unwrap()
+        .get(index)
+        .cloned()
+        .ok_or_else(|| LoxError::RuntimeError {
+            line: None,
+            msg: format!("List index {index} is out of bounds"),
+        })
+}
+
+pub fn head(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let list = get_list(&arguments)?;
+
+    list.read()
+        .unwrap()
+        .first()
+        .cloned()
+        .ok_or_else(|| LoxError::RuntimeError {
+            line: None,
+            msg: "head of an empty list".into(),
+        })
+}
+
+pub fn tail(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let list = get_list(&arguments)?.read().unwrap();
+
+    match list.split_first() {
+        Some((_, rest)) => Ok(LoxObject::from(rest.to_vec())),
+        None => Err(LoxError::RuntimeError {
+            line: None,
+            msg: "tail of an empty list".into(),
+        }),
+    }
+}
+
+// Elements from start up to, but not including, end
+pub fn slice(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let list = get_list(&arguments)?.read().unwrap();
+    let start = arguments[1].as_usize()?;
+    let end = arguments[2].as_usize()?;
+
+    list.get(start..end)
+        .map(|elements| LoxObject::from(elements.to_vec()))
+        .ok_or_else(|| LoxError::RuntimeError {
+            line: None,
+            msg: format!("Can't slice {start}..{end} of a list of {}", list.len()),
+        })
+}
+
+// Numbers or strings, in place. The list is also returned.
+pub fn sort(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    sort_with(&arguments[0], |a, b| match (a, b) {
+        (LoxObject::Number(a), LoxObject::Number(b)) => {
+            Ok(a.partial_cmp(b).unwrap_or(Ordering::Equal))
+        }
+        (LoxObject::LoxString(a), LoxObject::LoxString(b)) => Ok(a.cmp(b)),
+        _ => Err(LoxError::TypeError {
+            excepted_type: "list of only numbers or only strings".into(),
+        }),
+    })
+}
+
+// Comparators return a negative number, zero or a positive one like `a - b`
+pub fn sort_by(
+    executor: &Executor,
+    site: &CallSite,
+    arguments: Vec<LoxObject>,
+) -> LoxResult<LoxObject> {
+    let LoxObject::Callable(comparator) = &arguments[1] else {
+        return Err(LoxError::RuntimeError {
+            line: site.line,
+            msg: "sort_by expects a function with two parameters".into(),
+        });
+    };
+
+    sort_with(&arguments[0], |a, b| {
+        match comparator.call(executor, vec![a.clone(), b.clone()])? {
+            LoxObject::Number(n) => Ok(n.cmp0().unwrap_or(Ordering::Equal)),
+            _ => Err(LoxError::RuntimeError {
+                line: site.line,
+                msg: "sort_by comparators must return a number".into(),
+            }),
+        }
+    })
+}
+
+// A stable sort that stops at the first failed comparison. The list isn't locked
+// while comparing, so comparators may read it.
+fn sort_with(
+    list: &LoxObject,
+    mut compare: impl FnMut(&LoxObject, &LoxObject) -> LoxResult<Ordering>,
+) -> LoxResult<LoxObject> {
+    let shared = get_list(std::slice::from_ref(list))?;
+    let mut elements = shared.read().unwrap().clone();
+    let mut error = None;
+
+    elements.sort_by(|a, b| {
+        if error.is_some() {
+            return Ordering::Equal;
+        }
+
+        compare(a, b).unwrap_or_else(|e| {
+            error = Some(e);
+            Ordering::Equal
+        })
+    });
+
+    if let Some(error) = error {
+        return Err(error);
+    }
+
+    *shared.write().unwrap() = elements;
+    Ok(list.clone())
+}
+
+pub fn list_min(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    extreme(&arguments, "list_min", |candidate, current| {
+        candidate < current
+    })
+}
+
+pub fn list_max(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    extreme(&arguments, "list_max", |candidate, current| {
+        candidate > current
+    })
+}
+
+fn extreme(
+    arguments: &[LoxObject],
+    name: &str,
+    replaces: fn(&Float, &Float) -> bool,
+) -> LoxResult<LoxObject> {
+    let list = get_list(arguments)?.read().unwrap();
+    let mut result: Option<&Float> = None;
+
+    for element in list.iter() {
+        let LoxObject::Number(n) = element else {
+            return Err(LoxError::TypeError {
+                excepted_type: "Number".into(),
+            });
+        };
+
+        if result.is_none_or(|current| replaces(n, current)) {
+            result = Some(n);
+        }
+    }
+
+    result
+        .map(LoxObject::from)
+        .ok_or_else(|| LoxError::RuntimeError {
+            line: None,
+            msg: format!("{name} of an empty list"),
+        })
+}
+
+fn get_list(arguments: &[LoxObject]) -> LoxResult<&Arc<RwLock<Vec<LoxObject>>>> {
+    if let Some(LoxObject::List(list)) = arguments.first() {
+        Ok(list)
+    } else {
+        Err(LoxError::TypeError {
+            excepted_type: "List".into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NUMBER_PREC;
+
+    fn numbers(values: &[i32]) -> LoxObject {
+        LoxObject::from(
+            values
+                .iter()
+                .map(|n| LoxObject::from(Float::with_val(NUMBER_PREC, *n)))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn test_head_tail_and_slice() {
+        let number = |n: i32| LoxObject::from(Float::with_val(NUMBER_PREC, n));
+
+        assert_eq!(head(vec![numbers(&[3, 1])]).unwrap(), number(3));
+        assert!(head(vec![numbers(&[])]).is_err());
+
+        assert_eq!(tail(vec![numbers(&[3, 1])]).unwrap(), numbers(&[1]));
+        assert_eq!(tail(vec![numbers(&[3])]).unwrap(), numbers(&[]));
+        assert!(tail(vec![numbers(&[])]).is_err());
+
+        let slice_of = |start, end| slice(vec![numbers(&[1, 2, 3]), number(start), number(end)]);
+        assert_eq!(slice_of(1, 3).unwrap(), numbers(&[2, 3]));
+        assert_eq!(slice_of(3, 3).unwrap(), numbers(&[]));
+        assert!(slice_of(2, 4).is_err());
+        assert!(slice_of(2, 1).is_err());
+        assert!(slice(vec![LoxObject::Nil, number(0), number(0)]).is_err());
+    }
+
+    #[test]
+    fn test_sort() {
+        let list = numbers(&[3, 1, 2]);
+
+        assert_eq!(sort(vec![list.clone()]).unwrap(), numbers(&[1, 2, 3]));
+        assert_eq!(list, numbers(&[1, 2, 3]));
+
+        let words = LoxObject::from(vec![LoxObject::from("b"), LoxObject::from("a")]);
+        assert_eq!(
+            sort(vec![words]).unwrap(),
+            LoxObject::from(vec![LoxObject::from("a"), LoxObject::from("b")])
+        );
+
+        let mixed = LoxObject::from(vec![LoxObject::from("b"), numbers(&[])]);
+        assert!(sort(vec![mixed.clone()]).is_err());
+        assert_eq!(
+            mixed,
+            LoxObject::from(vec![LoxObject::from("b"), numbers(&[])])
+        );
+    }
+
+    #[test]
+    fn test_min_and_max() {
+        let three = LoxObject::from(Float::with_val(NUMBER_PREC, 3));
+        let one = LoxObject::from(Float::with_val(NUMBER_PREC, 1));
+
+        assert_eq!(list_max(vec![numbers(&[3, 1, 2])]).unwrap(), three);
+        assert_eq!(list_min(vec![numbers(&[3, 1, 2])]).unwrap(), one);
+        assert!(list_max(vec![numbers(&[])]).is_err());
+        assert!(list_min(vec![LoxObject::from(vec![LoxObject::from("a")])]).is_err());
+    }
+
+    #[test]
+    fn test_push_and_get() {
+        let l = list(vec![]).unwrap();
+        list_push(vec![l.clone(), LoxObject::from("first")]).unwrap();
+
+        assert_eq!(
+            list_get(vec![
+                l.clone(),
+                LoxObject::from(Float::with_val(NUMBER_PREC, 0))
+            ])
+            .unwrap(),
+            LoxObject::from("first")
+        );
+        assert!(list_get(vec![l, LoxObject::from(Float::with_val(NUMBER_PREC, 1))]).is_err());
+    }
+
+    #[test]
+    fn test_fractional_index() {
+        let l = LoxObject::from(vec![LoxObject::Nil]);
+
+        assert!(list_get(vec![l, LoxObject::from(Float::with_val(NUMBER_PREC, 0.5))]).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use crate::executor::{keyed::Keyed, CallSite, Executor, LoxObject};
+use crate::{LoxError, LoxResult};
+
+// Like sets, maps are keyed by the string form of the key.
+pub fn map(_: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    Ok(LoxObject::Map(Arc::new(Keyed::default())))
+}
+
+pub fn map_set(
+    executor: &Executor,
+    _: &CallSite,
+    arguments: Vec<LoxObject>,
+) -> LoxResult<LoxObject> {
+    get_map(&arguments)?.insert(executor, &arguments[1], arguments[2].clone())?;
+
+    Ok(arguments[2].clone())
+}
+
+pub fn map_get(
+    executor: &Executor,
+    _: &CallSite,
+    arguments: Vec<LoxObject>,
+) -> LoxResult<LoxObject> {
+    let value = get_map(&arguments)?.get(executor, &arguments[1])?;
+
+    Ok(value.unwrap_or(LoxObject::Nil))
+}
+
+pub fn has_key(
+    executor: &Executor,
+    _: &CallSite,
+    arguments: Vec<LoxObject>,
+) -> LoxResult<LoxObject> {
+    let value = get_map(&arguments)?.get(executor, &arguments[1])?;
+
+    Ok(LoxObject::from(value.is_some()))
+}
+
+pub fn keys(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let entries = sorted_entries(&arguments)?;
+
+    Ok(LoxObject::from(
+        entries.into_iter().map(|(key, _)| key).collect::<Vec<_>>(),
+    ))
+}
+
+pub fn values(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let entries = sorted_entries(&arguments)?;
+
+    Ok(LoxObject::from(
+        entries
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+// Each entry is a `[key, value]` list
+pub fn entries(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let entries = sorted_entries(&arguments)?;
+
+    Ok(LoxObject::from(
+        entries
+            .into_iter()
+            .map(|(key, value)| LoxObject::from(vec![key, value]))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+// Sorted by printed key, so the order doesn't depend on the hasher
+fn sorted_entries(arguments: &[LoxObject]) -> LoxResult<Vec<(LoxObject, LoxObject)>> {
+    let mut entries = get_map(arguments)?.entries();
+    entries.sort_by_cached_key(|(key, _)| key.to_string());
+
+    Ok(entries)
+}
+
+fn get_map(arguments: &[LoxObject]) -> LoxResult<&Keyed<LoxObject>> {
+    if let Some(LoxObject::Map(map)) = arguments.first() {
+        Ok(map)
+    } else {
+        Err(LoxError::TypeError {
+            excepted_type: "Map".into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::callable::ExecutorFn;
+    use crate::WORKERS;
+
+    fn call(native: ExecutorFn, arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+        native(&Executor::new(&WORKERS), &CallSite::default(), arguments)
+    }
+
+    #[test]
+    fn test_set_and_get() {
+        let m = map(vec![]).unwrap();
+        call(
+            map_set,
+            vec![m.clone(), LoxObject::from("key"), LoxObject::from(true)],
+        )
+        .unwrap();
+
+        assert_eq!(
+            call(map_get, vec![m.clone(), LoxObject::from("key")]).unwrap(),
+            LoxObject::from(true)
+        );
+        assert_eq!(
+            call(map_get, vec![m, LoxObject::from("missing")]).unwrap(),
+            LoxObject::Nil
+        );
+    }
+
+    #[test]
+    fn test_keys_values_and_entries() {
+        let m = map(vec![]).unwrap();
+        for (key, value) in [("pear", 3.), ("apple", 1.), ("fig", 2.)] {
+            call(
+                map_set,
+                vec![
+                    m.clone(),
+                    LoxObject::from(key),
+                    LoxObject::from(rug::Float::with_val(crate::NUMBER_PREC, value)),
+                ],
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            keys(vec![m.clone()]).unwrap().to_string(),
+            "[apple, fig, pear]"
+        );
+        assert_eq!(values(vec![m.clone()]).unwrap().to_string(), "[1, 2, 3]");
+        assert_eq!(
+            entries(vec![m.clone()]).unwrap().to_string(),
+            "[[apple, 1], [fig, 2], [pear, 3]]"
+        );
+        assert_eq!(
+            call(has_key, vec![m.clone(), LoxObject::from("fig")]).unwrap(),
+            LoxObject::from(true)
+        );
+        assert_eq!(
+            call(has_key, vec![m, LoxObject::from("plum")]).unwrap(),
+            LoxObject::from(false)
+        );
+        assert!(keys(vec![LoxObject::Nil]).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use rug::Float;
+
+use crate::{executor::LoxObject, LoxError, LoxResult, NUMBER_PREC};
+
+pub fn clamp(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let x = get_number(&arguments[0])?;
+    let lo = get_number(&arguments[1])?;
+    let hi = get_number(&arguments[2])?;
+
+    if lo > hi {
+        return Err(LoxError::RuntimeError {
+            line: None,
+            msg: format!("clamp bounds are reversed: {lo} is greater than {hi}"),
+        });
+    }
+
+    Ok(LoxObject::from(
+        Float::with_val(NUMBER_PREC, x).clamp(lo, hi),
+    ))
+}
+
+pub fn sign(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let x = get_number(&arguments[0])?;
+
+    let sign = match x.cmp0() {
+        Some(ordering) => ordering as i8,
+        None => {
+            return Err(LoxError::RuntimeError {
+                line: None,
+                msg: "Can't take the sign of NaN".into(),
+            })
+        }
+    };
+
+    Ok(LoxObject::from(Float::with_val(NUMBER_PREC, sign)))
+}
+
+pub fn trunc(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let x = get_number(&arguments[0])?;
+
+    Ok(LoxObject::from(Float::with_val(NUMBER_PREC, x.trunc_ref())))
+}
+
+pub fn floor(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let x = get_number(&arguments[0])?;
+
+    Ok(LoxObject::from(Float::with_val(NUMBER_PREC, x.floor_ref())))
+}
+
+pub fn ceil(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let x = get_number(&arguments[0])?;
+
+    Ok(LoxObject::from(Float::with_val(NUMBER_PREC, x.ceil_ref())))
+}
+
+pub fn round(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let x = get_number(&arguments[0])?;
+
+    Ok(LoxObject::from(Float::with_val(NUMBER_PREC, x.round_ref())))
+}
+
+pub fn abs(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let x = get_number(&arguments[0])?;
+
+    Ok(LoxObject::from(Float::with_val(NUMBER_PREC, x.abs_ref())))
+}
+
+// Floored, so the result has the sign of the divisor, unlike `%`
+pub fn floor_mod(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let a = get_number(&arguments[0])?;
+    let b = get_number(&arguments[1])?;
+
+    if b.is_zero() {
+        return Err(LoxError::RuntimeError {
+            line: None,
+            msg: "floor_mod by zero".into(),
+        });
+    }
+
+    let quotient = Float::with_val(NUMBER_PREC, a / b).floor();
+
+    Ok(LoxObject::from(Float::with_val(
+        NUMBER_PREC,
+        a - quotient * b,
+    )))
+}
+
+pub fn to_hex(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    to_radix(&arguments[0], 16, "0x")
+}
+
+pub fn to_bin(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    to_radix(&arguments[0], 2, "0b")
+}
+
+fn to_radix(object: &LoxObject, radix: i32, prefix: &str) -> LoxResult<LoxObject> {
+    let n = object.as_integer()?;
+
+    if n < 0 {
+        return Err(LoxError::RuntimeError {
+            line: None,
+            msg: format!("Can't format negative number {n} as {prefix}"),
+        });
+    }
+
+    Ok(LoxObject::from(
+        format!("{prefix}{}", n.to_string_radix(radix)).as_str(),
+    ))
+}
+
+pub fn get_number(object: &LoxObject) -> LoxResult<&Float> {
+    if let LoxObject::Number(n) = object {
+        Ok(n)
+    } else {
+        Err(LoxError::TypeError {
+            excepted_type: "Number".into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(n: f64) -> LoxObject {
+        LoxObject::from(Float::with_val(NUMBER_PREC, n))
+    }
+
+    #[test]
+    fn test_clamp() {
+        assert_eq!(
+            clamp(vec![number(5.), number(0.), number(3.)]).unwrap(),
+            number(3.)
+        );
+        assert_eq!(
+            clamp(vec![number(-1.), number(0.), number(3.)]).unwrap(),
+            number(0.)
+        );
+        assert_eq!(
+            clamp(vec![number(2.), number(0.), number(3.)]).unwrap(),
+            number(2.)
+        );
+        assert!(clamp(vec![number(2.), number(3.), number(0.)]).is_err());
+        assert!(clamp(vec![LoxObject::from("2"), number(0.), number(3.)]).is_err());
+    }
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(sign(vec![number(-2.)]).unwrap(), number(-1.));
+        assert_eq!(sign(vec![number(0.)]).unwrap(), number(0.));
+        assert_eq!(sign(vec![number(7.5)]).unwrap(), number(1.));
+    }
+
+    #[test]
+    fn test_rounding() {
+        assert_eq!(floor(vec![number(3.7)]).unwrap(), number(3.));
+        assert_eq!(ceil(vec![number(3.2)]).unwrap(), number(4.));
+        assert_eq!(round(vec![number(2.5)]).unwrap(), number(3.));
+        assert_eq!(abs(vec![number(-2.)]).unwrap(), number(2.));
+    }
+
+    #[test]
+    fn test_to_hex_and_to_bin() {
+        assert_eq!(to_hex(vec![number(255.)]).unwrap(), LoxObject::from("0xff"));
+        assert_eq!(
+            to_bin(vec![number(10.)]).unwrap(),
+            LoxObject::from("0b1010")
+        );
+        assert_eq!(to_hex(vec![number(0.)]).unwrap(), LoxObject::from("0x0"));
+        assert!(to_hex(vec![number(2.5)]).is_err());
+        assert!(to_bin(vec![number(-1.)]).is_err());
+    }
+
+    #[test]
+    fn test_floor_mod() {
+        assert_eq!(
+            floor_mod(vec![number(-7.), number(3.)]).unwrap(),
+            number(2.)
+        );
+        assert_eq!(
+            floor_mod(vec![number(7.), number(-3.)]).unwrap(),
+            number(-2.)
+        );
+        assert_eq!(floor_mod(vec![number(7.), number(3.)]).unwrap(), number(1.));
+        assert_eq!(
+            floor_mod(vec![number(5.5), number(2.)]).unwrap(),
+            number(1.5)
+        );
+        assert!(floor_mod(vec![number(1.), number(0.)]).is_err());
+    }
+
+    #[test]
+    fn test_trunc() {
+        assert_eq!(trunc(vec![number(3.9)]).unwrap(), number(3.));
+        assert_eq!(trunc(vec![number(-3.9)]).unwrap(), number(-3.));
+    }
+}
@@ -0,0 +1,20 @@
+use crate::executor::{callable::NativeFn, LoxObject};
+use crate::LoxResult;
+
+use super::math;
+
+// Methods of numbers, the number itself is the first argument
+pub fn find_method(name: &str) -> Option<(usize, NativeFn)> {
+    match name {
+        "floor" => Some((0, math::floor)),
+        "ceil" => Some((0, math::ceil)),
+        "round" => Some((0, math::round)),
+        "abs" => Some((0, math::abs)),
+        "to_string" => Some((0, to_string)),
+        _ => None,
+    }
+}
+
+fn to_string(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    Ok(LoxObject::from(arguments[0].to_string().as_str()))
+}
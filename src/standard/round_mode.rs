@@ -0,0 +1,32 @@
+use rug::float::Round;
+
+use crate::executor::{CallSite, Executor, LoxObject};
+use crate::{LoxError, LoxResult};
+
+pub fn set_round_mode(
+    executor: &Executor,
+    _: &CallSite,
+    arguments: Vec<LoxObject>,
+) -> LoxResult<LoxObject> {
+    executor.set_round_mode(parse_round_mode(&arguments[0])?);
+    Ok(LoxObject::Nil)
+}
+
+pub fn parse_round_mode(mode: &LoxObject) -> LoxResult<Round> {
+    if let LoxObject::LoxString(mode) = mode {
+        match mode.as_str() {
+            "nearest" => Ok(Round::Nearest),
+            "down" => Ok(Round::Down),
+            "up" => Ok(Round::Up),
+            "zero" => Ok(Round::Zero),
+            _ => Err(LoxError::RuntimeError {
+                line: None,
+                msg: format!("Unknown rounding mode '{mode}'"),
+            }),
+        }
+    } else {
+        Err(LoxError::TypeError {
+            excepted_type: "String".into(),
+        })
+    }
+}
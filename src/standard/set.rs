@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use crate::executor::{keyed::Keyed, CallSite, Executor, LoxObject};
+use crate::{LoxError, LoxResult};
+
+// Values are keyed by their string form, so 1 and "1" are the same member.
+pub fn set(_: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    Ok(LoxObject::Set(Arc::new(Keyed::default())))
+}
+
+pub fn set_add(
+    executor: &Executor,
+    _: &CallSite,
+    arguments: Vec<LoxObject>,
+) -> LoxResult<LoxObject> {
+    get_set(&arguments)?.insert(executor, &arguments[1], ())?;
+
+    Ok(LoxObject::Nil)
+}
+
+pub fn set_has(
+    executor: &Executor,
+    _: &CallSite,
+    arguments: Vec<LoxObject>,
+) -> LoxResult<LoxObject> {
+    let member = get_set(&arguments)?.get(executor, &arguments[1])?;
+
+    Ok(LoxObject::from(member.is_some()))
+}
+
+pub fn set_remove(
+    executor: &Executor,
+    _: &CallSite,
+    arguments: Vec<LoxObject>,
+) -> LoxResult<LoxObject> {
+    let removed = get_set(&arguments)?.remove(executor, &arguments[1])?;
+
+    Ok(LoxObject::from(removed.is_some()))
+}
+
+fn get_set(arguments: &[LoxObject]) -> LoxResult<&Keyed<()>> {
+    if let Some(LoxObject::Set(set)) = arguments.first() {
+        Ok(set)
+    } else {
+        Err(LoxError::TypeError {
+            excepted_type: "Set".into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::callable::ExecutorFn;
+    use crate::WORKERS;
+
+    fn call(native: ExecutorFn, arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+        native(&Executor::new(&WORKERS), &CallSite::default(), arguments)
+    }
+
+    fn new_set() -> LoxObject {
+        set(vec![]).unwrap()
+    }
+
+    #[test]
+    fn test_duplicates_counted_once() {
+        let s = new_set();
+
+        for value in ["a", "a", "b"] {
+            call(set_add, vec![s.clone(), LoxObject::from(value)]).unwrap();
+        }
+
+        if let LoxObject::Set(inner) = &s {
+            assert_eq!(inner.len(), 2);
+        } else {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_membership() {
+        let s = new_set();
+        call(set_add, vec![s.clone(), LoxObject::from(true)]).unwrap();
+
+        assert_eq!(
+            call(set_has, vec![s.clone(), LoxObject::from(true)]).unwrap(),
+            LoxObject::from(true)
+        );
+        assert_eq!(
+            call(set_has, vec![s, LoxObject::from(false)]).unwrap(),
+            LoxObject::from(false)
+        );
+    }
+
+    #[test]
+    fn test_remove() {
+        let s = new_set();
+        call(set_add, vec![s.clone(), LoxObject::from("x")]).unwrap();
+
+        assert_eq!(
+            call(set_remove, vec![s.clone(), LoxObject::from("x")]).unwrap(),
+            LoxObject::from(true)
+        );
+        assert_eq!(
+            call(set_has, vec![s.clone(), LoxObject::from("x")]).unwrap(),
+            LoxObject::from(false)
+        );
+        assert_eq!(
+            call(set_remove, vec![s, LoxObject::from("x")]).unwrap(),
+            LoxObject::from(false)
+        );
+    }
+
+    #[test]
+    fn test_non_set_argument() {
+        assert!(call(set_add, vec![LoxObject::Nil, LoxObject::Nil]).is_err())
+    }
+}
@@ -0,0 +1,226 @@
+use crate::executor::{callable::NativeFn, LoxObject};
+use crate::{LoxError, LoxResult};
+
+use super::len;
+
+// Methods of strings, the string itself is the first argument
+pub fn find_method(name: &str) -> Option<(usize, NativeFn)> {
+    match name {
+        "upper" => Some((0, upper)),
+        "len" => Some((0, len::len)),
+        "split" => Some((1, split)),
+        "contains" => Some((1, contains)),
+        _ => None,
+    }
+}
+
+pub fn upper(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let string = get_string(&arguments[0])?;
+
+    Ok(LoxObject::from(string.to_uppercase().as_str()))
+}
+
+pub fn split(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let string = get_string(&arguments[0])?;
+    let separator = get_string(&arguments[1])?;
+
+    Ok(LoxObject::from(
+        string
+            .split(separator)
+            .map(LoxObject::from)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+pub fn contains(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let string = get_string(&arguments[0])?;
+    let pattern = get_string(&arguments[1])?;
+
+    Ok(LoxObject::from(string.contains(pattern)))
+}
+
+pub fn rjust(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let (string, padding) = get_padding(&arguments)?;
+
+    Ok(LoxObject::from(format!("{padding}{string}").as_str()))
+}
+
+pub fn ljust(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let (string, padding) = get_padding(&arguments)?;
+
+    Ok(LoxObject::from(format!("{string}{padding}").as_str()))
+}
+
+// Fills `{}` with the other arguments in order, `{{` and `}}` are literal braces
+pub fn format(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let template = get_string(&arguments[0])?;
+    let mut values = arguments[1..].iter();
+    let mut result = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        match (char, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                result.push(char);
+            }
+            ('{', Some('}')) => {
+                chars.next();
+
+                match values.next() {
+                    Some(value) => result.push_str(&value.to_string()),
+                    None => return Err(format_error(template, arguments.len() - 1)),
+                }
+            }
+            ('{' | '}', _) => {
+                return Err(LoxError::RuntimeError {
+                    line: None,
+                    msg: format!("Unmatched '{char}' in format string \"{template}\""),
+                })
+            }
+            _ => result.push(char),
+        }
+    }
+
+    if values.next().is_some() {
+        return Err(format_error(template, arguments.len() - 1));
+    }
+
+    Ok(LoxObject::from(result.as_str()))
+}
+
+fn format_error(template: &str, given: usize) -> LoxError {
+    let placeholders = template.replace("{{", "").matches("{}").count();
+
+    LoxError::RuntimeError {
+        line: None,
+        msg: format!(
+            "Format string \"{template}\" has {placeholders} placeholders but {given} arguments were given"
+        ),
+    }
+}
+
+// The pad is a single character, nil pads with spaces
+fn get_padding(arguments: &[LoxObject]) -> LoxResult<(&str, String)> {
+    let string = get_string(&arguments[0])?;
+    let width = arguments[1].as_usize()?;
+    let pad = match &arguments[2] {
+        LoxObject::Nil => ' ',
+        pad => {
+            let mut chars = get_string(pad)?.chars();
+
+            match (chars.next(), chars.next()) {
+                (Some(pad), None) => pad,
+                _ => {
+                    return Err(LoxError::RuntimeError {
+                        line: None,
+                        msg: format!("Padding must be a single character, got '{pad}'"),
+                    })
+                }
+            }
+        }
+    };
+
+    let length = string.chars().count();
+
+    Ok((string, pad.to_string().repeat(width.saturating_sub(length))))
+}
+
+fn get_string(object: &LoxObject) -> LoxResult<&str> {
+    if let LoxObject::LoxString(string) = object {
+        Ok(string)
+    } else {
+        Err(LoxError::TypeError {
+            excepted_type: "String".into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let format_with =
+            |arguments: Vec<&str>| format(arguments.into_iter().map(LoxObject::from).collect());
+
+        assert_eq!(
+            format_with(vec!["{} + {} = {}", "1", "2", "3"]).unwrap(),
+            LoxObject::from("1 + 2 = 3")
+        );
+        assert_eq!(
+            format_with(vec!["{{{}}} {{}}", "x"]).unwrap(),
+            LoxObject::from("{x} {}")
+        );
+        assert_eq!(
+            format_with(vec!["plain"]).unwrap(),
+            LoxObject::from("plain")
+        );
+
+        let error = format_with(vec!["{} and {}", "1"]).unwrap_err();
+        assert!(
+            error.to_string().contains("2 placeholders but 1"),
+            "{error}"
+        );
+        let error = format_with(vec!["{}", "1", "2"]).unwrap_err();
+        assert!(
+            error.to_string().contains("1 placeholders but 2"),
+            "{error}"
+        );
+        assert!(format_with(vec!["{ }"]).is_err());
+        assert!(format_with(vec!["}"]).is_err());
+        assert!(format(vec![LoxObject::Nil]).is_err());
+    }
+
+    #[test]
+    fn test_upper() {
+        assert_eq!(
+            upper(vec![LoxObject::from("abc")]).unwrap(),
+            LoxObject::from("ABC")
+        );
+    }
+
+    #[test]
+    fn test_split() {
+        let parts = split(vec![LoxObject::from("a,b,c"), LoxObject::from(",")]).unwrap();
+
+        assert_eq!(parts.to_string(), "[a, b, c]");
+    }
+
+    #[test]
+    fn test_contains() {
+        assert_eq!(
+            contains(vec![LoxObject::from("hello"), LoxObject::from("ell")]).unwrap(),
+            LoxObject::from(true)
+        );
+        assert!(contains(vec![LoxObject::from("hello"), LoxObject::Nil]).is_err());
+    }
+
+    #[test]
+    fn test_justify() {
+        let justify = |fun: NativeFn, width: f64, pad: LoxObject| {
+            fun(vec![
+                LoxObject::from("ab"),
+                LoxObject::from(rug::Float::with_val(crate::NUMBER_PREC, width)),
+                pad,
+            ])
+        };
+
+        assert_eq!(
+            justify(rjust, 5., LoxObject::Nil).unwrap(),
+            LoxObject::from("   ab")
+        );
+        assert_eq!(
+            justify(ljust, 5., LoxObject::from("*")).unwrap(),
+            LoxObject::from("ab***")
+        );
+        assert_eq!(
+            justify(rjust, 1., LoxObject::Nil).unwrap(),
+            LoxObject::from("ab")
+        );
+        assert!(justify(rjust, 5., LoxObject::from("**")).is_err());
+        assert!(justify(rjust, 5., LoxObject::from("")).is_err());
+        assert!(justify(ljust, 2.5, LoxObject::Nil).is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use lazy_static::lazy_static;
+use rug::Float;
+
+use std::time::Instant;
+
+use crate::{executor::LoxObject, LoxError, LoxResult, NUMBER_PREC};
+
+lazy_static! {
+    // Instants are opaque, so timers count nanoseconds from this one
+    static ref EPOCH: Instant = Instant::now();
+}
+
+pub fn timer_start(_: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    Ok(LoxObject::from(Float::with_val(NUMBER_PREC, now())))
+}
+
+pub fn timer_elapsed(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    if let LoxObject::Number(start) = &arguments[0] {
+        let nanos = Float::with_val(NUMBER_PREC, now()) - start.as_ref();
+
+        Ok(LoxObject::from(nanos / 1_000_000))
+    } else {
+        Err(LoxError::TypeError {
+            excepted_type: "Number".into(),
+        })
+    }
+}
+
+fn now() -> u128 {
+    EPOCH.elapsed().as_nanos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn test_elapsed_after_sleep() {
+        let start = timer_start(vec![]).unwrap();
+        thread::sleep(Duration::from_millis(20));
+
+        let elapsed = timer_elapsed(vec![start]).unwrap();
+
+        assert!(elapsed
+            .is_greater_equal(&LoxObject::from(Float::with_val(NUMBER_PREC, 20)))
+            .map(|result| bool::from(&result))
+            .unwrap());
+    }
+}
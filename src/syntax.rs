@@ -5,23 +5,55 @@ use crate::LoxError;
 use crate::LoxResult;
 use crate::Token;
 use crate::TokenType;
+use crate::NUMBER_PREC;
 pub use expression::Expression;
-use expression::LoxLiteral;
-pub use statement::Statement;
+use expression::{LoxLiteral, Operator};
+pub use statement::{Poolable, Statement};
 
 use rug::Float;
 
 use std::sync::Arc;
 
+const MAX_ARGUMENTS: usize = 255;
+
+// What happens to a final expression without a semicolon
+#[derive(Debug, Copy, Clone)]
+enum Trailing {
+    Forbidden,
+    Printed,
+    Kept,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Parser<'a> {
     tokens: &'a [Token],
     current: usize,
+    trailing: Trailing,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(tokens: &'a [Token]) -> Self {
-        Self { tokens, current: 0 }
+        Self {
+            tokens,
+            current: 0,
+            trailing: Trailing::Forbidden,
+        }
+    }
+
+    // In the REPL, a final expression may omit its semicolon and gets printed
+    pub fn new_repl(tokens: &'a [Token]) -> Self {
+        Self {
+            trailing: Trailing::Printed,
+            ..Self::new(tokens)
+        }
+    }
+
+    // For `eval`, a final expression may omit its semicolon and gives the result
+    pub fn new_eval(tokens: &'a [Token]) -> Self {
+        Self {
+            trailing: Trailing::Kept,
+            ..Self::new(tokens)
+        }
     }
 
     pub fn parse(&mut self) -> LoxResult<Arc<Vec<Arc<Statement>>>> {
@@ -41,6 +73,20 @@ impl<'a> Parser<'a> {
         Ok(Arc::new(statements))
     }
 
+    pub fn parse_expression(&mut self) -> LoxResult<Expression> {
+        let expr = self.expression()?;
+
+        if let Some(token) = self.peek() {
+            return Err(LoxError::ParseError {
+                line: Some(token.line),
+                span: None,
+                msg: format!("Except end of expression, found {token}"),
+            });
+        }
+
+        Ok(expr)
+    }
+
     fn declaration(&mut self) -> LoxResult<Statement> {
         use TokenType::{AwaitVar, Class, Fun, Var};
 
@@ -58,7 +104,7 @@ impl<'a> Parser<'a> {
     }
 
     fn class_declaration(&mut self) -> LoxResult<Statement> {
-        use TokenType::{Identifier, LeftBrace, Less, RightBrace};
+        use TokenType::{Class, Identifier, LeftBrace, Less, RightBrace, Var};
 
         let name = self
             .consume(Identifier(String::new()), Some("Except class name.".into()))?
@@ -76,11 +122,16 @@ impl<'a> Parser<'a> {
 
         self.consume(LeftBrace, Some("Except '{' before class body.".into()))?;
 
-        let methods = {
+        let members = {
             let mut result = vec![];
 
             while !self.check(&RightBrace) && self.peek().is_some() {
-                result.push(self.function("method")?);
+                if self.is_match(&[Class]) {
+                    self.consume(Var, Some("Except 'var' after 'class'.".into()))?;
+                    result.push(self.var_declaration(Var)?);
+                } else {
+                    result.push(self.function("method")?);
+                }
             }
 
             result
@@ -88,11 +139,11 @@ impl<'a> Parser<'a> {
 
         self.consume(RightBrace, Some("Except '}' after class body.".into()))?;
 
-        Ok(Statement::Class(name, superclass, methods))
+        Ok(Statement::Class(name, superclass, members))
     }
 
     fn function(&mut self, kind: &str) -> LoxResult<Statement> {
-        use TokenType::{Comma, Identifier, LeftBrace, LeftParen, RightParen};
+        use TokenType::{Arrow, Comma, Identifier, LeftBrace, LeftParen, RightParen, Semicolon};
 
         let name = self
             .consume(
@@ -116,20 +167,21 @@ impl<'a> Parser<'a> {
                 );
 
                 while self.is_match(&[Comma]) {
-                    if result.len() >= 255 {
-                        return Err(LoxError::RuntimeError {
-                            line: Some(self.previous().line),
-                            msg: "Can't have more than 255 parameters.".into(),
-                        });
-                    }
-
                     result.push(
                         self.consume(
                             Identifier(String::new()),
                             Some("Except parameter name.".into()),
                         )
                         .map(|i| i.to_owned())?,
-                    )
+                    );
+
+                    if result.len() > MAX_ARGUMENTS {
+                        return Err(LoxError::ParseError {
+                            line: Some(self.previous().line),
+                            span: None,
+                            msg: format!("Can't have more than {MAX_ARGUMENTS} parameters."),
+                        });
+                    }
                 }
             }
 
@@ -138,6 +190,19 @@ impl<'a> Parser<'a> {
             result
         };
 
+        // `fun square(x) => x * x;` is a shorthand for `{ return x * x; }`
+        if self.is_match(&[Arrow]) {
+            let value = self.expression()?;
+            self.consume(Semicolon, Some(format!("Need ';' after {kind} body")))?;
+
+            let body = Statement::Block(
+                Arc::new(vec![Arc::new(Statement::Return(Some(Arc::new(value))))]),
+                Poolable::default(),
+            );
+
+            return Ok(Statement::Function(name, parameters, body.into()));
+        }
+
         self.consume(LeftBrace, Some(format!("Expect '{{' before {kind} body.")))?;
 
         let body = self.block_statement()?;
@@ -146,8 +211,14 @@ impl<'a> Parser<'a> {
     }
 
     fn var_declaration(&mut self, token_type: TokenType) -> LoxResult<Statement> {
-        use TokenType::Semicolon;
+        let declaration = self.var_declarator(token_type)?;
+        self.consume(TokenType::Semicolon, None)?;
+
+        Ok(declaration)
+    }
 
+    // A single `name = initializer`, without the semicolon
+    fn var_declarator(&mut self, token_type: TokenType) -> LoxResult<Statement> {
         let name_result = self
             .consume(TokenType::Identifier(String::new()), None)
             .map(|token| token.to_owned());
@@ -159,8 +230,6 @@ impl<'a> Parser<'a> {
                 initializer = Some(self.expression()?);
             }
 
-            self.consume(Semicolon, None)?;
-
             use TokenType::{AwaitVar, Var};
             match token_type {
                 Var => Ok(Statement::Var(name, initializer.map(Arc::new))),
@@ -168,6 +237,7 @@ impl<'a> Parser<'a> {
                     Some(init) => Ok(Statement::AwaitVar(name, init)),
                     None => Err(LoxError::ParseError {
                         line: Some(name.line),
+                        span: None,
                         msg: "Await expects initializer. Use 'Var' syntax without initializer."
                             .into(),
                     }),
@@ -190,8 +260,16 @@ impl<'a> Parser<'a> {
             self.print_statement()
         } else if self.is_match(&[Return]) {
             self.return_statement()
+        } else if self.is_match(&[Continue]) {
+            self.continue_statement()
+        } else if self.is_match(&[Break]) {
+            self.break_statement()
+        } else if self.is_match(&[Import]) {
+            self.import_statement()
         } else if self.is_match(&[While]) {
             self.while_statement()
+        } else if self.is_match(&[Repeat]) {
+            self.repeat_statement()
         } else if self.is_match(&[LeftBrace]) {
             self.block_statement()
         } else {
@@ -204,15 +282,24 @@ impl<'a> Parser<'a> {
 
         self.consume(LeftParen, None)?;
 
-        let initializer = {
-            if self.is_match(&[Semicolon]) {
-                None
-            } else if self.is_match(&[Var, AwaitVar]) {
-                Some(self.var_declaration(AwaitVar)?)
-            } else {
-                Some(self.expression_statement()?)
+        // Initializers and increments may be separated with commas
+        let mut initializers = vec![];
+
+        if self.is_match(&[Var, AwaitVar]) {
+            loop {
+                initializers.push(Arc::new(self.var_declarator(AwaitVar)?));
+
+                if !self.is_match(&[Comma]) {
+                    break;
+                }
             }
-        };
+        } else if !self.check(&Semicolon) {
+            for expr in self.expression_list()? {
+                initializers.push(Arc::new(Statement::StmtExpression(expr)));
+            }
+        }
+
+        self.consume(Semicolon, None)?;
 
         let condition = {
             if !self.check(&Semicolon) {
@@ -224,34 +311,39 @@ impl<'a> Parser<'a> {
 
         self.consume(Semicolon, None)?;
 
-        let increment = {
-            if !self.check(&RightParen) {
-                Some(self.expression()?)
-            } else {
-                None
-            }
+        let increments = if self.check(&RightParen) {
+            vec![]
+        } else {
+            self.expression_list()?
         };
 
         self.consume(RightParen, None)?;
 
-        let mut body = self.statement()?;
+        let body = self.statement()?;
 
-        if let Some(expr) = increment {
-            body = Statement::Block(Arc::new(vec![
-                Arc::new(body),
-                Arc::new(Statement::StmtExpression(expr)),
-            ]));
-        }
+        // The increments are kept apart from the body, so 'continue' doesn't skip them
+        let mut body = Statement::While(
+            condition.unwrap_or(Expression::Literal(LoxLiteral::Bool(true))),
+            body.into(),
+            increments,
+        );
 
-        if let Some(expr) = condition {
-            body = Statement::While(expr, body.into())
+        if !initializers.is_empty() {
+            initializers.push(Arc::new(body));
+            body = Statement::Block(Arc::new(initializers), Poolable::default())
         }
 
-        if let Some(stmt) = initializer {
-            body = Statement::Block(Arc::new(vec![Arc::new(stmt), Arc::new(body)]))
+        Ok(body)
+    }
+
+    fn expression_list(&mut self) -> LoxResult<Vec<Expression>> {
+        let mut expressions = vec![self.expression()?];
+
+        while self.is_match(&[TokenType::Comma]) {
+            expressions.push(self.expression()?);
         }
 
-        Ok(body)
+        Ok(expressions)
     }
 
     fn if_statement(&mut self) -> LoxResult<Statement> {
@@ -304,6 +396,25 @@ impl<'a> Parser<'a> {
         Ok(Statement::Return(value))
     }
 
+    fn continue_statement(&mut self) -> LoxResult<Statement> {
+        let keyword = self.previous().to_owned();
+
+        self.consume(
+            TokenType::Semicolon,
+            Some("Need ';' after 'continue'".into()),
+        )?;
+
+        Ok(Statement::Continue(keyword))
+    }
+
+    fn break_statement(&mut self) -> LoxResult<Statement> {
+        let keyword = self.previous().to_owned();
+
+        self.consume(TokenType::Semicolon, Some("Need ';' after 'break'".into()))?;
+
+        Ok(Statement::Break(keyword))
+    }
+
     fn while_statement(&mut self) -> LoxResult<Statement> {
         use TokenType::{LeftParen, RightParen};
 
@@ -312,7 +423,55 @@ impl<'a> Parser<'a> {
         self.consume(RightParen, None)?;
         let body = self.statement()?;
 
-        Ok(Statement::While(condition, body.into()))
+        Ok(Statement::While(condition, body.into(), vec![]))
+    }
+
+    fn repeat_statement(&mut self) -> LoxResult<Statement> {
+        use TokenType::{LeftParen, RightParen};
+
+        let keyword = self.previous().to_owned();
+
+        self.consume(LeftParen, Some("Except '(' after 'repeat'".into()))?;
+        let count = self.expression()?;
+        self.consume(RightParen, Some("Except ')' after repeat count".into()))?;
+        let body = self.statement()?;
+
+        Ok(Statement::Repeat(keyword, count, body.into()))
+    }
+
+    fn import_statement(&mut self) -> LoxResult<Statement> {
+        let keyword = self.previous().to_owned();
+
+        let path = match &self
+            .consume(
+                TokenType::LoxString(String::new()),
+                Some("Except a path after 'import'".into()),
+            )?
+            .kind
+        {
+            TokenType::LoxString(path) => path.to_owned(),
+            _ => unreachable!(),
+        };
+
+        // `as` is only special here, so it is still a valid name elsewhere
+        let namespace = if self.check(&TokenType::Identifier(String::new()))
+            && self.peek().unwrap().kind == TokenType::Identifier("as".into())
+        {
+            self.advance();
+            Some(
+                self.consume(
+                    TokenType::Identifier(String::new()),
+                    Some("Except a name after 'as'".into()),
+                )?
+                .to_owned(),
+            )
+        } else {
+            None
+        };
+
+        self.consume(TokenType::Semicolon, Some("Need ';' after import".into()))?;
+
+        Ok(Statement::Import(keyword, path, namespace))
     }
 
     fn block_statement(&mut self) -> LoxResult<Statement> {
@@ -326,12 +485,21 @@ impl<'a> Parser<'a> {
 
         self.consume(RightBrace, None)?;
 
-        Ok(Statement::Block(Arc::new(statements)))
+        Ok(Statement::Block(Arc::new(statements), Poolable::default()))
     }
 
     fn expression_statement(&mut self) -> LoxResult<Statement> {
         let expr = self.expression()?;
 
+        if self.peek().is_none() {
+            match self.trailing {
+                // Printing is the statement itself, so the expression still runs only once
+                Trailing::Printed => return Ok(Statement::Print(expr)),
+                Trailing::Kept => return Ok(Statement::StmtExpression(expr)),
+                Trailing::Forbidden => (),
+            }
+        }
+
         self.consume(TokenType::Semicolon, None)?;
 
         Ok(Statement::StmtExpression(expr))
@@ -347,6 +515,7 @@ impl<'a> Parser<'a> {
         let expr = self.lambda()?;
 
         if self.is_match(&[Equal]) {
+            let equals = self.previous().span;
             let value = self.assignment()?;
 
             if let Expression::Variable(name) = expr {
@@ -356,6 +525,7 @@ impl<'a> Parser<'a> {
             } else {
                 return Err(LoxError::ParseError {
                     line: Some(self.previous().line),
+                    span: Some(equals),
                     msg: "Invalid assignment target.".to_string(),
                 });
             }
@@ -384,20 +554,21 @@ impl<'a> Parser<'a> {
                     );
 
                     while self.is_match(&[Comma]) {
-                        if result.len() >= 255 {
-                            return Err(LoxError::RuntimeError {
-                                line: Some(self.previous().line),
-                                msg: "Can't have more than 255 parameters.".into(),
-                            });
-                        }
-
                         result.push(
                             self.consume(
                                 Identifier(String::new()),
                                 Some("Except parameter name.".into()),
                             )
                             .map(|i| i.to_owned())?,
-                        )
+                        );
+
+                        if result.len() > MAX_ARGUMENTS {
+                            return Err(LoxError::ParseError {
+                                line: Some(self.previous().line),
+                                span: None,
+                                msg: format!("Can't have more than {MAX_ARGUMENTS} parameters."),
+                            });
+                        }
                     }
                 }
 
@@ -408,8 +579,14 @@ impl<'a> Parser<'a> {
             self.consume(LeftBrace, Some("Except '{' before lambda body".into()))?;
 
             let body = self.block_statement()?;
+            let mut expr = Expression::Lambda(parameters, body.into());
 
-            Ok(Expression::Lambda(parameters, body.into()))
+            // `lambda(x) { ... }(4)` calls it right away
+            while self.is_match(&[LeftParen]) {
+                expr = self.finish_call(expr)?;
+            }
+
+            Ok(expr)
         } else {
             self.or()
         }
@@ -462,11 +639,33 @@ impl<'a> Parser<'a> {
     fn comparison(&mut self) -> LoxResult<Expression> {
         use TokenType::*;
 
-        let mut expr = self.term()?;
+        let expr = self.shift()?;
+        let mut rest = vec![];
 
         while self.is_match(&[Greater, GreaterEqual, Less, LessEqual]) {
+            let operator = self.previous().try_into()?;
+            rest.push((operator, self.shift()?));
+        }
+
+        Ok(match rest.len() {
+            0 => expr,
+            1 => {
+                let (operator, right) = rest.remove(0);
+                Expression::Binary(expr.into(), operator, right.into())
+            }
+            _ => Expression::Comparison(expr.into(), rest),
+        })
+    }
+
+    fn shift(&mut self) -> LoxResult<Expression> {
+        use TokenType::*;
+
+        let mut expr = self.term()?;
+
+        while self.is_match(&[LessLess, GreaterGreater, GreaterGreaterGreater]) {
             let operator = self.previous().try_into()?;
             let right = self.term()?;
+
             expr = Expression::Binary(expr.into(), operator, right.into());
         }
 
@@ -482,7 +681,15 @@ impl<'a> Parser<'a> {
             let operator = self.previous().try_into()?;
             let right = self.factor()?;
 
-            expr = Expression::Binary(expr.into(), operator, right.into());
+            // Joined strings become a single literal
+            expr = match (expr, operator, right) {
+                (
+                    Expression::Literal(LoxLiteral::LoxString(left)),
+                    Operator::Plus,
+                    Expression::Literal(LoxLiteral::LoxString(right)),
+                ) => Expression::Literal(LoxLiteral::LoxString(left + &right)),
+                (expr, operator, right) => Expression::Binary(expr.into(), operator, right.into()),
+            };
         }
 
         Ok(expr)
@@ -493,7 +700,7 @@ impl<'a> Parser<'a> {
 
         let mut expr = self.unary()?;
 
-        while self.is_match(&[Slash, Star]) {
+        while self.is_match(&[Slash, Star, Percent]) {
             let operator = self.previous().try_into()?;
             let right = self.unary()?;
 
@@ -510,10 +717,64 @@ impl<'a> Parser<'a> {
             let operator = self.previous().try_into()?;
             let right = self.unary()?;
 
-            return Ok(Expression::Unary(operator, right.into()));
+            // Negative numbers become a single literal
+            return Ok(match (operator, right) {
+                (Operator::Minus, Expression::Literal(LoxLiteral::Number(n))) => {
+                    Expression::Literal(LoxLiteral::Number(-n))
+                }
+                (operator, right) => Expression::Unary(operator, right.into()),
+            });
+        }
+
+        if self.is_match(&[PlusPlus, MinusMinus]) {
+            let operator = self.previous().to_owned();
+            let target = self.unary()?;
+
+            return self.increment(target, &operator, true);
         }
 
-        self.call()
+        let expr = self.call()?;
+
+        if self.is_match(&[PlusPlus, MinusMinus]) {
+            let operator = self.previous().to_owned();
+
+            return self.increment(expr, &operator, false);
+        }
+
+        Ok(expr)
+    }
+
+    // `++x` and `x++` both store `x ± 1`, the postfix form returns the value read before
+    fn increment(
+        &mut self,
+        target: Expression,
+        operator: &Token,
+        prefix: bool,
+    ) -> LoxResult<Expression> {
+        let Expression::Variable(name) = target else {
+            return Err(LoxError::ParseError {
+                line: Some(operator.line),
+                span: None,
+                msg: format!("Can only apply '{}' to a variable.", operator.kind),
+            });
+        };
+
+        let step = match operator.kind {
+            TokenType::PlusPlus => Operator::Plus,
+            _ => Operator::Minus,
+        };
+        let one = Expression::Literal(LoxLiteral::Number(Float::with_val(NUMBER_PREC, 1)));
+
+        let assign = Expression::Assign(
+            name.clone(),
+            Box::new(Expression::Binary(
+                Box::new(Expression::Variable(name)),
+                step,
+                Box::new(one),
+            )),
+        );
+
+        Ok(Expression::Increment(Box::new(assign), prefix))
     }
 
     fn call(&mut self) -> LoxResult<Expression> {
@@ -544,16 +805,18 @@ impl<'a> Parser<'a> {
         let mut arguments = vec![];
 
         if !self.check(&RightParen) {
-            arguments.push(self.expression()?);
+            arguments.push(Arc::new(self.expression()?));
 
             while self.is_match(&[Comma]) {
-                if arguments.len() >= 255 {
-                    return Err(LoxError::RuntimeError {
+                arguments.push(Arc::new(self.expression()?));
+
+                if arguments.len() > MAX_ARGUMENTS {
+                    return Err(LoxError::ParseError {
                         line: Some(self.previous().line),
-                        msg: "Can't have more than 255 arguments.".to_string(),
+                        span: None,
+                        msg: format!("Can't have more than {MAX_ARGUMENTS} arguments."),
                     });
                 }
-                arguments.push(self.expression()?)
             }
         }
 
@@ -600,6 +863,9 @@ impl<'a> Parser<'a> {
         if self.is_match(&[This]) {
             return Ok(Expression::This(self.previous().to_owned()));
         }
+        if self.is_match(&[Match]) {
+            return self.match_expression();
+        }
         if self.is_match(&[Identifier(String::new())]) {
             return Ok(Expression::Variable(self.previous().to_owned()));
         }
@@ -608,14 +874,63 @@ impl<'a> Parser<'a> {
 
             self.consume(RightParen, None)?;
 
-            return Ok(Expression::Grouping(Box::new(expr)));
+            // Parentheses around a single name or literal change nothing,
+            // so `(x)` parses and resolves just like `x`
+            return Ok(match expr {
+                Expression::Variable(..) | Expression::Literal(..) | Expression::Grouping(..) => {
+                    expr
+                }
+                _ => Expression::Grouping(Box::new(expr)),
+            });
         }
 
-        Err(LoxError::ExceptedExpression(if self.current == 0 {
-            0
-        } else {
-            self.previous().line
-        }))
+        Err(LoxError::ExceptedExpression(
+            self.previous().line,
+            Some(self.current().span),
+        ))
+    }
+
+    fn match_expression(&mut self) -> LoxResult<Expression> {
+        use TokenType::*;
+
+        let keyword = self.previous().to_owned();
+        let value = self.expression()?;
+        self.consume(LeftBrace, Some("Except '{' after match value".into()))?;
+
+        let mut arms = vec![];
+
+        while !self.check(&RightBrace) {
+            let pattern = if self.check(&Identifier("_".into()))
+                && self
+                    .peek()
+                    .is_some_and(|token| token.kind == Identifier("_".into()))
+            {
+                self.advance();
+                None
+            } else {
+                match self.unary()? {
+                    Expression::Literal(literal) => Some(literal),
+                    _ => {
+                        return Err(LoxError::ParseError {
+                            line: Some(self.previous().line),
+                            span: Some(self.previous().span),
+                            msg: "Match patterns must be literals or '_'".into(),
+                        })
+                    }
+                }
+            };
+
+            self.consume(Arrow, Some("Except '=>' after match pattern".into()))?;
+            arms.push((pattern, self.expression()?));
+
+            if !self.is_match(&[Comma]) {
+                break;
+            }
+        }
+
+        self.consume(RightBrace, Some("Except '}' after match arms".into()))?;
+
+        Ok(Expression::Match(keyword, value.into(), arms))
     }
 
     fn consume(&mut self, token_type: TokenType, msg: Option<String>) -> LoxResult<&Token> {
@@ -626,7 +941,11 @@ impl<'a> Parser<'a> {
 
         Err(LoxError::ParseError {
             line: Some(self.previous().line),
-            msg: msg.unwrap_or(format!("{:?} not found", token_type)),
+            span: Some(self.current().span),
+            msg: msg.unwrap_or_else(|| match token_type {
+                TokenType::Identifier(_) => "Expected identifier".into(),
+                other => format!("Expected '{other}'"),
+            }),
         })
     }
 
@@ -641,7 +960,7 @@ impl<'a> Parser<'a> {
             };
 
             match val.kind {
-                Class | Fun | Var | For | If | While | Print | Return => return,
+                Class | Fun | Var | For | If | While | Repeat | Print | Return | Import => return,
                 _ => {
                     self.advance();
                 }
@@ -682,8 +1001,16 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // The token that is looked at, EOF at the end
+    fn current(&self) -> &Token {
+        self.tokens
+            .get(self.current)
+            .unwrap_or_else(|| self.previous())
+    }
+
+    // Falls back to the first token, so errors at the start of input still have a line
     fn previous(&self) -> &Token {
-        self.tokens.get(self.current - 1).unwrap()
+        self.tokens.get(self.current.saturating_sub(1)).unwrap()
     }
 }
 
@@ -711,10 +1038,16 @@ mod tests {
 
     #[test]
     fn test_minus_unary_expression() {
-        assert_eq!(
-            create_expression("-4").unwrap(),
-            Expression::Unary(Operator::Minus, create_number(4))
-        )
+        assert_eq!(create_expression("-4").unwrap(), *create_number(-4));
+        assert_eq!(create_expression("- -4").unwrap(), *create_number(4));
+        assert!(matches!(
+            create_expression("-x").unwrap(),
+            Expression::Unary(Operator::Minus, _)
+        ));
+        assert!(matches!(
+            create_expression("-4.abs()").unwrap(),
+            Expression::Unary(Operator::Minus, _)
+        ));
     }
 
     #[test]
@@ -750,7 +1083,7 @@ mod tests {
     fn test_block_statement() {
         assert_eq!(
             create_statement("{}").unwrap(),
-            Statement::Block(Arc::new(vec![]))
+            Statement::Block(Arc::new(vec![]), Poolable::default())
         )
     }
 
@@ -791,4 +1124,249 @@ mod tests {
     //         )
     //     )
     // }
+
+    fn call_with_arguments(count: usize) -> String {
+        format!("f({})", vec!["1"; count].join(", "))
+    }
+
+    #[test]
+    fn test_255_arguments() {
+        assert!(create_expression(&call_with_arguments(255)).is_ok())
+    }
+
+    #[test]
+    fn test_256_arguments() {
+        assert!(matches!(
+            create_expression(&call_with_arguments(256)),
+            Err(LoxError::ParseError { line: Some(1), msg, .. }) if msg == "Can't have more than 255 arguments."
+        ))
+    }
+
+    #[test]
+    fn test_256_parameters() {
+        let params = (0..256).map(|i| format!("p{i}")).collect::<Vec<_>>();
+
+        assert!(create_statement(&format!("fun f({}) {{}}", params[..255].join(", "))).is_ok());
+        assert!(matches!(
+            create_statement(&format!("fun f({}) {{}}", params.join(", "))),
+            Err(LoxError::ParseError { .. })
+        ))
+    }
+
+    #[test]
+    fn test_error_spans() {
+        use crate::scanner::Span;
+
+        assert!(matches!(
+            create_expression("1 + )"),
+            Err(LoxError::ExceptedExpression(
+                1,
+                Some(Span { start: 4, end: 5 })
+            ))
+        ));
+        assert!(matches!(
+            create_expression("(1 + 2"),
+            Err(LoxError::ParseError {
+                span: Some(Span { start: 6, end: 6 }),
+                ..
+            })
+        ));
+        assert!(matches!(
+            create_expression("a + b = 3"),
+            Err(LoxError::ParseError {
+                span: Some(Span { start: 6, end: 7 }),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_error_on_first_token() {
+        use crate::Scanner;
+
+        let tokens = Scanner::new(")").scan_tokens().unwrap();
+
+        assert!(matches!(
+            Parser::new(&tokens).consume(TokenType::Semicolon, None),
+            Err(LoxError::ParseError { line: Some(1), .. })
+        ));
+        assert!(matches!(
+            Parser::new(&tokens).parse(),
+            Err(LoxError::ExceptedExpression(1, _))
+        ));
+    }
+
+    #[test]
+    fn test_empty_expression() {
+        assert!(matches!(
+            create_expression(""),
+            Err(LoxError::ExceptedExpression(1, _))
+        ));
+    }
+
+    #[test]
+    fn test_compound_statements_need_no_semicolon() {
+        use crate::Scanner;
+
+        let parse = |source| Parser::new(&Scanner::new(source).scan_tokens().unwrap()).parse();
+
+        let statements =
+            parse("{ f(); } g(); if (x) h(); else { i(); } while (x) {} { { j(); } } k();")
+                .unwrap();
+        let kinds: Vec<&str> = statements.iter().map(|stmt| stmt.kind()).collect();
+
+        assert_eq!(
+            kinds,
+            ["block", "expression", "if", "while", "block", "expression"]
+        );
+
+        assert!(parse("{ f() }").is_err());
+        assert!(parse("f() { }").is_err());
+        assert!(parse("if (x) f() g();").is_err());
+    }
+
+    #[test]
+    fn test_match_expression() {
+        let Expression::Match(_, value, arms) =
+            create_expression("match x { 1 => 2, _ => 3 }").unwrap()
+        else {
+            panic!("not a match expression");
+        };
+        assert!(matches!(*value, Expression::Variable(..)));
+        assert!(matches!(
+            arms.as_slice(),
+            [(Some(LoxLiteral::Number(..)), _), (None, _)]
+        ));
+        assert!(create_expression("match x { 1 + 1 => 2 }").is_err());
+        assert!(create_expression("match x { 1 => 2").is_err());
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        assert_eq!(
+            create_expression("\"foo\" + \"bar\" + \"baz\"").unwrap(),
+            Expression::Literal(LoxLiteral::LoxString("foobarbaz".into()))
+        );
+        assert!(matches!(
+            create_expression("\"foo\" + 1").unwrap(),
+            Expression::Binary(..)
+        ));
+    }
+
+    #[test]
+    fn test_redundant_grouping() {
+        // The resolved depths are checked in test_parenthesized_assignment_resolves_like_plain
+        assert!(matches!(
+            create_expression("(x) = 1").unwrap(),
+            Expression::Assign(..)
+        ));
+        assert!(matches!(
+            create_expression("((x))").unwrap(),
+            Expression::Variable(..)
+        ));
+        assert!(matches!(
+            create_expression("(1 + 2)").unwrap(),
+            Expression::Grouping(..)
+        ));
+    }
+
+    #[test]
+    fn test_property_on_number_literal() {
+        let get_on_two = |source| {
+            let expr = create_expression(source).unwrap();
+
+            if let Expression::Get(object, name) = &expr {
+                let object = match object.as_ref() {
+                    Expression::Grouping(inner) => inner.as_ref(),
+                    other => other,
+                };
+
+                assert_eq!(object, create_number(2).as_ref(), "{source}");
+                assert_eq!(name.kind, TokenType::Identifier("foo".into()), "{source}");
+            } else {
+                panic!("{source} parsed as {expr}")
+            }
+        };
+
+        get_on_two("2.foo");
+        get_on_two("2 .foo");
+        get_on_two("(2).foo");
+
+        assert!(matches!(
+            create_expression("2.0").unwrap(),
+            Expression::Literal(LoxLiteral::Number(_))
+        ));
+    }
+
+    #[test]
+    fn test_arrow_function() {
+        let function = create_statement("fun square(x) => x * x;").unwrap();
+
+        if let Statement::Function(_, parameters, body) = function {
+            assert_eq!(parameters.len(), 1);
+
+            if let Statement::Block(statements, _) = body.as_ref() {
+                assert!(matches!(statements.as_slice(), [statement]
+                    if matches!(statement.as_ref(), Statement::Return(Some(value))
+                        if value.to_string() == create_expression("x * x").unwrap().to_string())));
+            } else {
+                panic!("Unexcepted body: {body:?}")
+            }
+        } else {
+            panic!("Unexcepted statement: {function:?}")
+        }
+
+        assert!(create_statement("fun square(x) => x * x").is_err());
+    }
+
+    #[test]
+    fn test_missing_token_message() {
+        let error = create_expression("(1 + 2").unwrap_err();
+
+        if let LoxError::ParseError { msg, .. } = error {
+            assert_eq!(msg, "Expected ')'");
+        } else {
+            panic!("Unexcepted error: {error}")
+        }
+    }
+
+    #[test]
+    fn test_chained_comparison() {
+        let expr = create_expression("0 < x <= 10 > y").unwrap();
+
+        if let Expression::Comparison(first, rest) = &expr {
+            assert_eq!(first.as_ref(), create_number(0).as_ref());
+            assert_eq!(
+                rest.iter()
+                    .map(|(operator, _)| *operator)
+                    .collect::<Vec<_>>(),
+                [
+                    Operator::Smaller,
+                    Operator::SmallerOrEqual,
+                    Operator::Greater
+                ]
+            );
+        } else {
+            panic!("Unexcepted expression: {expr}")
+        }
+
+        assert!(matches!(
+            create_expression("0 < x").unwrap(),
+            Expression::Binary(..)
+        ));
+    }
+
+    #[test]
+    fn test_import() {
+        assert!(matches!(
+            create_statement("import \"math.lox\";").unwrap(),
+            Statement::Import(_, path, None) if path == "math.lox"
+        ));
+        assert!(matches!(
+            create_statement("import \"math.lox\" as m;").unwrap(),
+            Statement::Import(_, _, Some(name)) if name.kind == TokenType::Identifier("m".into())
+        ));
+        assert!(create_statement("import math;").is_err());
+        assert!(create_statement("var as = 1;").is_ok());
+    }
 }
@@ -4,23 +4,34 @@ use std::sync::Arc;
 use rug::Float;
 
 use super::Statement;
+use crate::executor::object::bool_to_str;
 use crate::{LoxError, Token, TokenType};
 
 #[derive(PartialEq, Eq, Hash, Debug)]
 pub enum Expression {
     Binary(Box<Expression>, Operator, Box<Expression>),
+    // `a < b <= c`, where every operand is evaluated once
+    Comparison(Box<Expression>, Vec<(Operator, Expression)>),
     Unary(Operator, Box<Expression>),
     Grouping(Box<Expression>),
     Literal(LoxLiteral),
     Logical(Box<Expression>, Operator, Box<Expression>),
     Variable(Token),
     Assign(Token, Box<Expression>),
-    Call(Box<Expression>, Token, Vec<Expression>),
+    // `x++` and `++x`, the assignment of `x ± 1` and whether the new value is returned
+    Increment(Box<Expression>, bool),
+    Call(Box<Expression>, Token, Vec<Arc<Expression>>),
     Lambda(Vec<Token>, Arc<Statement>),
     Get(Box<Expression>, Token),
     Set(Box<Expression>, Token, Box<Expression>),
     This(Token),
     Super(Token, Token),
+    // `match value { 1 => a, _ => b }`, arms without a pattern are `_`
+    Match(
+        Token,
+        Box<Expression>,
+        Vec<(Option<LoxLiteral>, Expression)>,
+    ),
 }
 
 impl Display for Expression {
@@ -31,6 +42,13 @@ impl Display for Expression {
             Binary(left, operator, right) => {
                 write!(f, "({operator} {left} {right})")
             }
+            Comparison(first, rest) => {
+                write!(f, "(chain {first}")?;
+                for (operator, operand) in rest {
+                    write!(f, " {operator} {operand}")?;
+                }
+                write!(f, ")")
+            }
             Unary(operator, right) => {
                 write!(f, "({operator} {right})")
             }
@@ -46,6 +64,9 @@ impl Display for Expression {
             Assign(token, value) => {
                 write!(f, "(assign {token} {value})")
             }
+            Increment(assign, prefix) => {
+                write!(f, "(increment {prefix} {assign})")
+            }
             Logical(left, operator, right) => {
                 write!(f, "({operator} {left} {right})")
             }
@@ -67,6 +88,16 @@ impl Display for Expression {
             Super(_keyword, method) => {
                 write!(f, "(super.{method})")
             }
+            Match(_keyword, value, arms) => {
+                write!(f, "(match {value}")?;
+                for (pattern, arm) in arms {
+                    match pattern {
+                        Some(pattern) => write!(f, " {pattern} => {arm}")?,
+                        None => write!(f, " _ => {arm}")?,
+                    }
+                }
+                write!(f, ")")
+            }
         }
     }
 }
@@ -87,7 +118,7 @@ impl Display for LoxLiteral {
             Nil => write!(f, "nil"),
             LoxString(s) => write!(f, r#""{s}""#),
             Number(n) => write!(f, "{n}"),
-            Bool(b) => write!(f, "{b}"),
+            Bool(b) => write!(f, "{}", bool_to_str(*b)),
         }
     }
 }
@@ -99,7 +130,7 @@ impl std::hash::Hash for LoxLiteral {
         match self {
             Nil => "NIL_LIT".hash(state),
             LoxString(str) => format!("LOX_LIT_STR_{str}").hash(state),
-            Bool(b) => format!("BOOL_LIT_{b}").hash(state),
+            Bool(b) => format!("BOOL_LIT_{}", bool_to_str(*b)).hash(state),
             Number(float) => format!("NUMBER_LIT_{float}").hash(state),
         }
     }
@@ -116,6 +147,10 @@ pub enum Operator {
     Plus,
     Star,
     Slash,
+    Remainder,
+    ShiftLeft,
+    ShiftRight,
+    UnsignedShiftRight,
     Not,
     Smaller,
     SmallerOrEqual,
@@ -137,6 +172,10 @@ impl TryInto<Operator> for &Token {
             TokenType::Minus => Ok(Operator::Minus),
             TokenType::Plus => Ok(Operator::Plus),
             TokenType::Star => Ok(Operator::Star),
+            TokenType::Percent => Ok(Operator::Remainder),
+            TokenType::LessLess => Ok(Operator::ShiftLeft),
+            TokenType::GreaterGreater => Ok(Operator::ShiftRight),
+            TokenType::GreaterGreaterGreater => Ok(Operator::UnsignedShiftRight),
             TokenType::Slash => Ok(Operator::Slash),
             TokenType::Bang => Ok(Operator::Not),
             TokenType::Greater => Ok(Operator::Greater),
@@ -168,6 +207,10 @@ impl Display for Operator {
                 Plus => "+",
                 Star => "*",
                 Slash => "/",
+                Remainder => "%",
+                ShiftLeft => "<<",
+                ShiftRight => ">>",
+                UnsignedShiftRight => ">>>",
                 Not => "!",
                 Smaller => "<",
                 SmallerOrEqual => "<=",
@@ -1,5 +1,7 @@
 use crate::Token;
-use std::{hash::Hash, sync::Arc};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use super::Expression;
 
@@ -9,18 +11,72 @@ pub enum Statement {
     StmtExpression(Expression),
     Var(Token, Option<Arc<Expression>>),
     AwaitVar(Token, Expression),
-    Block(Arc<Vec<Arc<Statement>>>),
+    Block(Arc<Vec<Arc<Statement>>>, Poolable),
     // Condition        If Branch      Else Branch
     If(Expression, Arc<Statement>, Option<Arc<Statement>>),
-    //     Condition     Body
-    While(Expression, Arc<Statement>),
+    //     Condition     Body        Increments
+    While(Expression, Arc<Statement>, Vec<Expression>),
+    //    Keyword   Count        Body
+    Repeat(Token, Expression, Arc<Statement>),
     //        Name     Params      Body
     Function(Token, Vec<Token>, Arc<Statement>),
     Return(Option<Arc<Expression>>),
+    Continue(Token),
+    Break(Token),
+    //     Keyword  Path    Namespace
+    Import(Token, String, Option<Token>),
     //    Name    Subclass       Methods
+    // Methods and `class var` fields, the latter as Var statements
     Class(Token, Option<Arc<Expression>>, Vec<Statement>),
 }
 
+impl Statement {
+    // Names the statement for traces, expressions are logged on their own
+    pub fn kind(&self) -> &'static str {
+        use Statement::*;
+
+        match self {
+            Print(..) => "print",
+            StmtExpression(..) => "expression",
+            Var(..) => "var",
+            AwaitVar(..) => "await_var",
+            Block(..) => "block",
+            If(..) => "if",
+            While(..) => "while",
+            Repeat(..) => "repeat",
+            Function(..) => "fun",
+            Return(..) => "return",
+            Continue(..) => "continue",
+            Break(..) => "break",
+            Import(..) => "import",
+            Class(..) => "class",
+        }
+    }
+}
+
+// Set by the resolver when no closure can capture the block's scope, so the executor may
+// take that scope from a pool. It isn't part of the syntax, so it never affects equality.
+#[derive(Debug, Default)]
+pub struct Poolable(AtomicBool);
+
+impl Poolable {
+    pub fn set(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for Poolable {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Poolable {}
+
 impl Hash for Statement {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         format!("{:?}", self).hash(state);
@@ -0,0 +1,50 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tarlox::interpret;
+
+// Counts every allocation of this test binary, so it holds a single test
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const ITERATIONS: usize = 1_000_000;
+
+fn allocations(source: &str) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    interpret(source).unwrap();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+#[test]
+fn test_loop_blocks_reuse_their_environment() {
+    // Sets up the globals and the workers first
+    interpret("var i = 0;").unwrap();
+
+    let bare = allocations(&format!(
+        "var i = 0; while (i < {ITERATIONS}) i = i + 1; assert(i == {ITERATIONS});"
+    ));
+    let block = allocations(&format!(
+        "var i = 0; while (i < {ITERATIONS}) {{ i = i + 1; }} assert(i == {ITERATIONS});"
+    ));
+
+    // A fresh environment takes three allocations, the block may only add a few in total
+    assert!(
+        block < bare + ITERATIONS / 100,
+        "{block} allocations with a block, {bare} without"
+    );
+}
@@ -0,0 +1,238 @@
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn test_non_utf8_file() {
+    let path = std::env::temp_dir().join(format!("tarlox-binary-{}.lox", std::process::id()));
+    fs::write(&path, [0xff, 0xfe, 0x00, 0x80]).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tarlox"))
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("UTF-8"), "{stdout}");
+    assert!(!stdout.contains("not found"), "{stdout}");
+    assert_eq!(output.status.code(), Some(65));
+}
+
+#[test]
+fn test_missing_file() {
+    let output = Command::new(env!("CARGO_BIN_EXE_tarlox"))
+        .arg("this/file/does/not/exist.lox")
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("File not found"), "{stdout}");
+}
+
+#[test]
+fn test_max_time() {
+    let path = std::env::temp_dir().join(format!("tarlox-forever-{}.lox", std::process::id()));
+    fs::write(&path, "while (true) {}").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tarlox"))
+        .arg("--max-time=0.1")
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("time limit"), "{stdout}");
+    assert_eq!(output.status.code(), Some(65));
+}
+
+#[test]
+fn test_pending_tasks() {
+    let path = std::env::temp_dir().join(format!("tarlox-pending-{}.lox", std::process::id()));
+    fs::write(
+        &path,
+        "await_var done = false;
+         print pending_tasks();
+         var futures = list();
+         list_push(futures, spawn(lambda() { while (!done) {} return 1; }));
+         print pending_tasks();
+         done = true;
+         print await_all(futures);",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tarlox"))
+        .arg("--max-time=10")
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "0\n1\n[1]\n");
+}
+
+#[test]
+fn test_eval_flag() {
+    let output = Command::new(env!("CARGO_BIN_EXE_tarlox"))
+        .arg("--eval=var a = 3; a + 4;")
+        .output()
+        .unwrap();
+
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "7\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_eval_flag_prints_only_expressions() {
+    let output = Command::new(env!("CARGO_BIN_EXE_tarlox"))
+        .arg("--eval=print 1;")
+        .output()
+        .unwrap();
+
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_debug_env() {
+    let output = Command::new(env!("CARGO_BIN_EXE_tarlox"))
+        .arg("--eval=var a = 1; { var b = 2; var c; debug_env(); }")
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("scope 0: 2 variables\n"), "{stderr}");
+    assert!(stderr.contains("scope 1: 1 variables\n"), "{stderr}");
+    assert_eq!(
+        stderr.lines().filter(|line| line.starts_with("  ")).count(),
+        3,
+        "{stderr}"
+    );
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_import() {
+    let directory = std::env::temp_dir().join(format!("tarlox-import-{}", std::process::id()));
+    fs::create_dir_all(directory.join("lib")).unwrap();
+
+    fs::write(
+        directory.join("main.lox"),
+        "import \"lib/greet.lox\"; import \"lib/greet.lox\"; print greet(\"Lox\");",
+    )
+    .unwrap();
+    // Imports are relative to the importing file, and importing the main script again does nothing
+    fs::write(
+        directory.join("lib/greet.lox"),
+        "import \"../main.lox\"; print \"loading\"; fun greet(name) { return \"Hello, \" + name; }",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tarlox"))
+        .arg(directory.join("main.lox"))
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "loading\nHello, Lox\n");
+}
+
+#[test]
+fn test_import_as_namespace() {
+    let directory = std::env::temp_dir().join(format!("tarlox-namespace-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+
+    fs::write(
+        directory.join("main.lox"),
+        "import \"math.lox\" as m;
+         import \"math.lox\" as again;
+         print m.square(3);
+         print m.answer;
+         print m == again;
+         assert_throws(lambda() { return square(3); });",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("math.lox"),
+        "var answer = 42; fun square(x) { return x * x; }",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tarlox"))
+        .arg(directory.join("main.lox"))
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "9\n42\ntrue\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_import_plain_and_as_namespace() {
+    let directory = std::env::temp_dir().join(format!("tarlox-both-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+
+    // Each file runs once, whichever kind of import comes first
+    fs::write(
+        directory.join("main.lox"),
+        "import \"a.lox\" as a;
+         import \"a.lox\";
+         import \"b.lox\";
+         import \"b.lox\" as b;
+         print a.first + first;
+         print b.second + second;",
+    )
+    .unwrap();
+    fs::write(directory.join("a.lox"), "print \"a\"; var first = 1;").unwrap();
+    fs::write(directory.join("b.lox"), "print \"b\"; var second = 2;").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tarlox"))
+        .arg(directory.join("main.lox"))
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "a\nb\n2\n4\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_sci_threshold_flag() {
+    let code = "--eval=var x = 1; for (var i = 0; i < 20; i = i + 1) x = x * 10; x / 4;";
+
+    for (threshold, expected) in [("21", "25000000000000000000\n"), ("19", "2.5e19\n")] {
+        let output = Command::new(env!("CARGO_BIN_EXE_tarlox"))
+            .arg(format!("--sci-threshold={threshold}"))
+            .arg(code)
+            .output()
+            .unwrap();
+
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tarlox"))
+        .arg("--sci-threshold=many")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(64));
+}
+
+#[test]
+fn test_errors_without_color() {
+    for (flag, no_color) in [("--no-color", None), ("--strict", Some("1"))] {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_tarlox"));
+        command.arg(flag).arg("--eval=1 + nil;");
+        if let Some(value) = no_color {
+            command.env("NO_COLOR", value);
+        }
+
+        let stdout = String::from_utf8(command.output().unwrap().stdout).unwrap();
+        assert!(stdout.contains("Can't add number and nil"), "{stdout}");
+        assert!(!stdout.contains('\x1b'), "{stdout}");
+    }
+}
@@ -0,0 +1,320 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use tarlox::{
+    eval, interpret, interpret_with_output, run, run_repl, Executor, LoxError, LoxObject,
+    LoxResult, Resolver, WORKERS,
+};
+
+#[test]
+fn test_eval_sum() {
+    assert_eq!(eval("1 + 1").unwrap().to_string(), "2");
+}
+
+#[test]
+fn test_eval_returns_number() {
+    assert_eq!(eval("3 + 4").unwrap(), eval("7").unwrap(),);
+    assert!(matches!(eval("3 + 4").unwrap(), LoxObject::Number(..)));
+}
+
+#[test]
+fn test_eval_rejects_trailing_tokens() {
+    assert!(eval("1 + 1; 2").is_err());
+}
+
+#[test]
+fn test_interpret_program() {
+    assert!(interpret("fun double(x) { return x * 2; } assert(double(2) == 4);").is_ok());
+}
+
+#[test]
+fn test_eval_string() {
+    assert_eq!(eval(r#""tar" + "lox""#).unwrap(), LoxObject::from("tarlox"));
+}
+
+#[test]
+fn test_print_to_buffer() {
+    let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+
+    interpret_with_output(r#"print "hi";"#, buffer.clone()).unwrap();
+
+    assert_eq!(buffer.lock().unwrap().as_slice(), b"hi\n");
+}
+
+#[test]
+fn test_instance_output_is_reproducible() {
+    let source = r#"
+        class Point {}
+        await_var p = Point();
+        print p;
+        print p == p;
+        print Point() == p;
+    "#;
+
+    let run = || {
+        let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+        interpret_with_output(source, buffer.clone()).unwrap();
+        let output = buffer.lock().unwrap().clone();
+
+        String::from_utf8(output).unwrap()
+    };
+
+    let first = run();
+
+    assert_eq!(first, run());
+    assert!(first.ends_with("true\nfalse\n"), "{first}");
+}
+
+#[test]
+fn test_repl_lines_with_comments() {
+    let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+    let executor = Executor::with_output(&WORKERS, buffer.clone());
+    let mut resolver = Resolver::new(&executor);
+
+    run("var a = 1; var b = 2; // setup", &mut resolver).unwrap();
+    run("print a; print b; // note", &mut resolver).unwrap();
+    run("print a + b;//", &mut resolver).unwrap();
+
+    assert_eq!(buffer.lock().unwrap().as_slice(), b"1\n2\n3\n");
+}
+
+#[test]
+fn test_repl_final_expression_without_semicolon() {
+    let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+    let executor = Executor::with_output(&WORKERS, buffer.clone());
+    let mut resolver = Resolver::new(&executor);
+
+    run_repl("1 + 1", &mut resolver).unwrap();
+    run_repl("var a = 3; a * 2", &mut resolver).unwrap();
+    assert!(run_repl("1 + 1 2", &mut resolver).is_err());
+    assert!(run("1 + 1", &mut resolver).is_err());
+
+    assert_eq!(buffer.lock().unwrap().as_slice(), b"2\n6\n");
+}
+
+static TICKS: AtomicUsize = AtomicUsize::new(0);
+
+fn tick(_: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    let ticks = TICKS.fetch_add(1, Ordering::SeqCst) + 1;
+    Ok(LoxObject::from(rug::Float::with_val(
+        tarlox::NUMBER_PREC,
+        ticks,
+    )))
+}
+
+#[test]
+fn test_repl_redefines_across_submissions() {
+    let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+    let executor = Executor::with_output(&WORKERS, buffer.clone());
+    let mut resolver = Resolver::new(&executor).incremental(true);
+
+    run_repl("fun greet() { return 1; }", &mut resolver).unwrap();
+    run_repl("await_var old = greet; var count = 1;", &mut resolver).unwrap();
+    run_repl("fun greet() { return 2; }", &mut resolver).unwrap();
+    run_repl("var count = 2;", &mut resolver).unwrap();
+    assert!(run_repl("var count = count + 1;", &mut resolver).is_err());
+    // A failed submission doesn't leave its scopes behind
+    assert!(run_repl("{ var a = 1; var a = 2; }", &mut resolver).is_err());
+    run_repl("count = count + 1;", &mut resolver).unwrap();
+    run_repl("fun bump() { count = count * 10; } bump();", &mut resolver).unwrap();
+    run_repl("print greet(); print old(); print count;", &mut resolver).unwrap();
+
+    assert_eq!(buffer.lock().unwrap().as_slice(), b"2\n1\n30\n");
+
+    // Scripts still can't declare a global twice
+    let mut resolver = Resolver::new(&executor);
+    assert!(run("fun twice() {} fun twice() {}", &mut resolver).is_err());
+}
+
+#[test]
+fn test_repl_prints_without_evaluating_twice() {
+    let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+    let executor = Executor::with_output(&WORKERS, buffer.clone());
+    executor.define_native("tick", 0, tick);
+    let mut resolver = Resolver::new(&executor);
+
+    run_repl("tick()", &mut resolver).unwrap();
+    assert_eq!(TICKS.load(Ordering::SeqCst), 1);
+
+    run_repl("var a = 1; tick() + a", &mut resolver).unwrap();
+    assert_eq!(TICKS.load(Ordering::SeqCst), 2);
+
+    assert_eq!(buffer.lock().unwrap().as_slice(), b"1\n3\n");
+}
+
+fn double(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    arguments[0].clone() + arguments[0].clone()
+}
+
+#[test]
+fn test_host_defined_native() {
+    let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+    let executor = Executor::with_output(&WORKERS, buffer.clone());
+    executor.define_native("double", 1, double);
+    let mut resolver = Resolver::new(&executor);
+
+    run(
+        "print double(21); fun f() { return double(2); } print f();",
+        &mut resolver,
+    )
+    .unwrap();
+    assert!(run("double(1, 2);", &mut resolver).is_err());
+    assert!(run("var double = 1;", &mut resolver).is_err());
+    run("{ var double = 1; }", &mut resolver).unwrap();
+
+    assert_eq!(buffer.lock().unwrap().as_slice(), b"42\n4\n");
+}
+
+fn run_strict(source: &str, strict: bool) -> LoxResult<()> {
+    let executor = Executor::with_output(&WORKERS, Arc::new(Mutex::new(std::io::sink())));
+    let mut resolver = Resolver::new(&executor).strict(strict);
+
+    run(source, &mut resolver)
+}
+
+#[test]
+fn test_strict_mode_rejects_assignment_conditions() {
+    assert!(run_strict("var x = 1; if (x = 5) print x;", true).is_err());
+    assert!(run_strict("var x = true; while (x = false) {}", true).is_err());
+    assert!(run_strict("var x = 1; if (x == 5) print x;", true).is_ok());
+    assert!(run_strict("var x = 1; if ((x = 5)) print x;", true).is_ok());
+    assert!(run_strict("var x = 1; if (x = 5) print x;", false).is_ok());
+}
+
+#[test]
+fn test_cancel_running_script() {
+    let executor = Executor::with_output(&WORKERS, Arc::new(Mutex::new(std::io::sink())));
+    let token = executor.cancel_handle();
+    let mut resolver = Resolver::new(&executor);
+
+    let canceller = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        token.cancel();
+    });
+
+    let result = run("var i = 0; while (true) { i = i + 1; }", &mut resolver);
+    canceller.join().unwrap();
+
+    assert!(matches!(result, Err(LoxError::Cancelled)), "{result:?}");
+}
+
+fn nap(arguments: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    thread::sleep(Duration::from_millis(300));
+    Ok(arguments[0].clone())
+}
+
+#[test]
+fn test_function_arguments_run_in_parallel() {
+    // A pool of our own, so other tests can't keep the workers busy
+    let workers = Box::leak(Box::new(threadpool::ThreadPool::new(2)));
+    let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+    let executor = Executor::with_output(workers, buffer.clone());
+    executor.define_pure_native("nap", 1, nap);
+    let mut resolver = Resolver::new(&executor);
+
+    run("fun pair(a, b) { return a + b; }", &mut resolver).unwrap();
+
+    let started = std::time::Instant::now();
+    run("print pair(nap(1), nap(2));", &mut resolver).unwrap();
+    assert!(started.elapsed() < Duration::from_millis(550));
+
+    let error = run("pair(nap(nil) + 1, missing);", &mut resolver).unwrap_err();
+    assert!(
+        matches!(error, LoxError::OperandTypeError { .. }),
+        "{error}"
+    );
+
+    assert_eq!(buffer.lock().unwrap().as_slice(), b"3\n");
+}
+
+#[test]
+fn test_side_effects_of_arguments_run_left_to_right() {
+    let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+    let executor = Executor::with_output(&WORKERS, buffer.clone());
+    let mut resolver = Resolver::new(&executor);
+
+    run(
+        "fun log(x) { print x; return x; }
+         fun pair(a, b) { return a + b; }
+         print pair(log(1), log(2));
+         print pair(log(3), log(4) + log(5));
+         { var len = log; print pair(len(6), len(7)); }",
+        &mut resolver,
+    )
+    .unwrap();
+
+    assert_eq!(
+        buffer.lock().unwrap().as_slice(),
+        b"1\n2\n3\n3\n4\n5\n12\n6\n7\n13\n"
+    );
+}
+
+static STACK_LEFT: AtomicUsize = AtomicUsize::new(0);
+
+fn record_stack(_: Vec<LoxObject>) -> LoxResult<LoxObject> {
+    STACK_LEFT.store(stacker::remaining_stack().unwrap(), Ordering::SeqCst);
+    Ok(LoxObject::from(rug::Float::with_val(
+        tarlox::NUMBER_PREC,
+        0,
+    )))
+}
+
+// How much stack is left when the program reaches `record_stack()`, in a fresh interpreter
+fn stack_left_at_base(program: &str) -> usize {
+    let executor = Executor::new(&WORKERS);
+    executor.define_native("record_stack", 0, record_stack);
+    let mut resolver = Resolver::new(&executor);
+
+    run(program, &mut resolver).unwrap();
+    STACK_LEFT.load(Ordering::SeqCst)
+}
+
+#[test]
+fn test_tail_calls_in_branches_keep_the_stack_flat() {
+    let countdown = |n: usize| {
+        stack_left_at_base(&format!(
+            "fun down(n) {{ if (n == 0) {{ return record_stack(); }} else return (down(n - 1)); }}
+             down({n});"
+        ))
+    };
+    let mutual = |n: usize| {
+        stack_left_at_base(&format!(
+            "fun even(n) {{ if (n == 0) return record_stack(); else return odd(n - 1); }}
+             fun odd(n) {{ if (n == 0) return false; {{ return even(n - 1); }} }}
+             even({n});"
+        ))
+    };
+    let nested = |n: usize| {
+        stack_left_at_base(&format!(
+            "fun down(n) {{ if (n == 0) return record_stack(); return 1 + down(n - 1); }}
+             down({n});"
+        ))
+    };
+
+    assert_eq!(countdown(1), countdown(500));
+    assert_eq!(mutual(2), mutual(500));
+    assert!(nested(50) < nested(1));
+}
+
+#[test]
+fn test_sci_threshold_per_executor() {
+    let source = "var x = 1; for (var i = 0; i < 20; i = i + 1) x = x * 10;
+                  var l = list(); list_push(l, x); print x; print l;";
+    let printed = |sci_threshold| {
+        let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let executor =
+            Executor::with_output(&WORKERS, buffer.clone()).with_sci_threshold(sci_threshold);
+        run(source, &mut Resolver::new(&executor)).unwrap();
+
+        let output = buffer.lock().unwrap().clone();
+        String::from_utf8(output).unwrap()
+    };
+
+    assert_eq!(printed(Some(5)), "1e20\n[1e20]\n");
+    assert_eq!(
+        printed(None),
+        "100000000000000000000\n[100000000000000000000]\n"
+    );
+}